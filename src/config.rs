@@ -0,0 +1,57 @@
+use crate::{actions::BindingsConfig, gamepad::Deadzone};
+use serde::{Deserialize, Serialize};
+use std::{fs, io::ErrorKind, path::Path};
+
+#[derive(Serialize, Deserialize)]
+pub struct GameConfig {
+    #[serde(default)]
+    pub bindings: BindingsConfig,
+    #[serde(default)]
+    pub buttons: BindingsConfig,
+    #[serde(default = "default_deadzone_inner")]
+    pub deadzone_inner: f64,
+    #[serde(default = "default_deadzone_outer")]
+    pub deadzone_outer: f64,
+}
+
+fn default_deadzone_inner() -> f64 {
+    Deadzone::default().inner
+}
+
+fn default_deadzone_outer() -> f64 {
+    Deadzone::default().outer
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            bindings: BindingsConfig::new(),
+            buttons: BindingsConfig::new(),
+            deadzone_inner: default_deadzone_inner(),
+            deadzone_outer: default_deadzone_outer(),
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn deadzone(&self) -> Deadzone {
+        Deadzone {
+            inner: self.deadzone_inner,
+            outer: self.deadzone_outer,
+        }
+    }
+
+    /// A missing file gives the default config
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+}
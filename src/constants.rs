@@ -1,3 +1,9 @@
 pub const SCREEN_WIDTH: u32 = 1920 / 4;
 pub const SCREEN_HEIGHT: u32 = 1080 / 4;
 pub const FPS: f64 = 1000.0 / 60.0;
+pub const TICK_RATE: f64 = 60.0;
+pub const MAX_TICKS_PER_FRAME: u32 = 5;
+pub const GAME_CONFIG_PATH: &str = "./gameconfig.json";
+pub const MAX_PLAYERS: usize = 4;
+pub const SAVE_DIR: &str = "./saves";
+pub const SAVE_SLOTS: u32 = 3;
@@ -30,7 +30,7 @@ pub enum EditorActions {
 }
 
 pub struct Editor {
-    pub ui: UIComponent<EditorProps, EditorActions>,
+    pub ui: UIComponent<'static, EditorProps, EditorActions>,
 }
 
 fn entity_select() -> ViewBuilder {
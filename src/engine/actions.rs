@@ -0,0 +1,290 @@
+use sdl2::{controller::Button, keyboard::Keycode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Confirm,
+    Cancel,
+    Menu,
+    QuickSave,
+    QuickLoad,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Confirm,
+        Action::Cancel,
+        Action::Menu,
+        Action::QuickSave,
+        Action::QuickLoad,
+    ];
+
+    pub fn default_keys(self) -> Vec<Keycode> {
+        match self {
+            Action::MoveUp => vec![Keycode::W, Keycode::Up],
+            Action::MoveDown => vec![Keycode::S, Keycode::Down],
+            Action::MoveLeft => vec![Keycode::A, Keycode::Left],
+            Action::MoveRight => vec![Keycode::D, Keycode::Right],
+            Action::Jump => vec![Keycode::Space],
+            Action::Confirm => vec![Keycode::Return, Keycode::Z],
+            Action::Cancel => vec![Keycode::Backspace, Keycode::X],
+            Action::Menu => vec![Keycode::Tab],
+            Action::QuickSave => vec![Keycode::F5],
+            Action::QuickLoad => vec![Keycode::F9],
+        }
+    }
+
+    pub fn default_buttons(self) -> Vec<Button> {
+        match self {
+            Action::MoveUp => vec![Button::DPadUp],
+            Action::MoveDown => vec![Button::DPadDown],
+            Action::MoveLeft => vec![Button::DPadLeft],
+            Action::MoveRight => vec![Button::DPadRight],
+            Action::Jump => vec![Button::A],
+            Action::Confirm => vec![Button::X],
+            Action::Cancel => vec![Button::B],
+            Action::Menu => vec![Button::Start],
+            Action::QuickSave | Action::QuickLoad => vec![],
+        }
+    }
+}
+
+/// Bindings as they are stored in gameconfig.json, action name to SDL key or controller button names
+pub type BindingsConfig = BTreeMap<Action, Vec<String>>;
+
+#[derive(Debug)]
+pub enum BindError {
+    /// The key is already bound to another action
+    Conflict(Keycode, Action),
+    /// The controller button is already bound to another action
+    ButtonConflict(Button, Action),
+}
+
+/// Named actions bound to one or more keys and controller buttons
+pub struct ActionMap {
+    bindings: HashMap<Action, Vec<Keycode>>,
+    buttons: HashMap<Action, Vec<Button>>,
+    dirty: bool,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        Self {
+            bindings: Action::ALL.iter().map(|a| (*a, a.default_keys())).collect(),
+            buttons: Action::ALL.iter().map(|a| (*a, a.default_buttons())).collect(),
+            dirty: false,
+        }
+    }
+}
+
+impl ActionMap {
+    /// Actions missing from the config keep their default keys
+    pub fn from_config(config: &BindingsConfig, buttons: &BindingsConfig) -> Result<Self, String> {
+        let mut ret = Self::default();
+
+        for (action, names) in buttons.iter() {
+            let mut action_buttons = vec![];
+            for name in names.iter() {
+                action_buttons.push(Button::from_string(name).ok_or(format!("Unknown button '{}' bound to {:?}", name, action))?);
+            }
+            ret.buttons.insert(*action, action_buttons);
+        }
+
+        for (action, names) in config.iter() {
+            let mut keys = vec![];
+            for name in names.iter() {
+                keys.push(Keycode::from_name(name).ok_or(format!("Unknown key '{}' bound to {:?}", name, action))?);
+            }
+            ret.bindings.insert(*action, keys);
+        }
+
+        for (action, keys) in ret.bindings.iter() {
+            for key in keys.iter() {
+                if let Some((other, _)) = ret.bindings.iter().find(|(a, k)| *a != action && k.contains(key)) {
+                    return Err(format!("Key '{}' is bound to both {:?} and {:?}", key.name(), action, other));
+                }
+            }
+        }
+
+        for (action, action_buttons) in ret.buttons.iter() {
+            for button in action_buttons.iter() {
+                if let Some((other, _)) = ret.buttons.iter().find(|(a, b)| *a != action && b.contains(button)) {
+                    return Err(format!("Button '{}' is bound to both {:?} and {:?}", button.string(), action, other));
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+
+    pub fn to_config(&self) -> BindingsConfig {
+        self.bindings
+            .iter()
+            .map(|(action, keys)| (*action, keys.iter().map(|k| k.name()).collect()))
+            .collect()
+    }
+
+    pub fn buttons_to_config(&self) -> BindingsConfig {
+        self.buttons
+            .iter()
+            .map(|(action, buttons)| (*action, buttons.iter().map(|b| b.string()).collect()))
+            .collect()
+    }
+
+    /// True when bindings changed since loading and should be saved
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn keys(&self, action: Action) -> &[Keycode] {
+        self.bindings.get(&action).map(|k| k.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn action_for(&self, keycode: Keycode) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&keycode))
+            .map(|(action, _)| *action)
+    }
+
+    /// Adds a key to an action, failing if another action already uses the key
+    pub fn bind(&mut self, action: Action, keycode: Keycode) -> Result<(), BindError> {
+        match self.action_for(keycode) {
+            Some(a) if a == action => return Ok(()),
+            Some(a) => return Err(BindError::Conflict(keycode, a)),
+            None => {}
+        }
+
+        self.bindings.entry(action).or_insert_with(Vec::new).push(keycode);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Adds a key to an action, taking it away from whatever action had it before
+    pub fn bind_replacing(&mut self, action: Action, keycode: Keycode) -> Option<Action> {
+        let previous = self.action_for(keycode).filter(|a| *a != action);
+
+        if let Some(a) = previous {
+            self.unbind(a, keycode);
+        }
+
+        self.bind(action, keycode).ok();
+        previous
+    }
+
+    pub fn unbind(&mut self, action: Action, keycode: Keycode) {
+        if let Some(keys) = self.bindings.get_mut(&action) {
+            let len = keys.len();
+            keys.retain(|k| *k != keycode);
+            self.dirty |= keys.len() != len;
+        }
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.bindings.insert(action, vec![]);
+        self.buttons.insert(action, vec![]);
+        self.dirty = true;
+    }
+
+    pub fn buttons(&self, action: Action) -> &[Button] {
+        self.buttons.get(&action).map(|b| b.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn action_for_button(&self, button: Button) -> Option<Action> {
+        self.buttons
+            .iter()
+            .find(|(_, buttons)| buttons.contains(&button))
+            .map(|(action, _)| *action)
+    }
+
+    /// Adds a controller button to an action, failing if another action already uses the button
+    pub fn bind_button(&mut self, action: Action, button: Button) -> Result<(), BindError> {
+        match self.action_for_button(button) {
+            Some(a) if a == action => return Ok(()),
+            Some(a) => return Err(BindError::ButtonConflict(button, a)),
+            None => {}
+        }
+
+        self.buttons.entry(action).or_insert_with(Vec::new).push(button);
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn unbind_button(&mut self, action: Action, button: Button) {
+        if let Some(buttons) = self.buttons.get_mut(&action) {
+            let len = buttons.len();
+            buttons.retain(|b| *b != button);
+            self.dirty |= buttons.len() != len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(Action, &[&str])]) -> BindingsConfig {
+        entries.iter().map(|(a, names)| (*a, names.iter().map(|n| n.to_string()).collect())).collect()
+    }
+
+    #[test]
+    fn merges_a_partial_config_with_the_defaults() {
+        let map = ActionMap::from_config(&config(&[(Action::Jump, &["J"])]), &config(&[(Action::Jump, &["y"])])).unwrap();
+
+        assert_eq!(map.keys(Action::Jump), &[Keycode::J]);
+        assert_eq!(map.buttons(Action::Jump), &[Button::Y]);
+        assert_eq!(map.keys(Action::MoveUp), Action::MoveUp.default_keys().as_slice());
+        assert_eq!(map.buttons(Action::Menu), Action::Menu.default_buttons().as_slice());
+        assert!(!map.is_dirty());
+    }
+
+    #[test]
+    fn rejects_conflicting_configs() {
+        // W is MoveUp's by default
+        assert!(ActionMap::from_config(&config(&[(Action::Jump, &["W"])]), &BindingsConfig::new()).is_err());
+        assert!(ActionMap::from_config(&BindingsConfig::new(), &config(&[(Action::Jump, &["start"])])).is_err());
+        assert!(ActionMap::from_config(&config(&[(Action::Jump, &["NotAKey"])]), &BindingsConfig::new()).is_err());
+
+        // Moving W away from MoveUp in the same config is fine
+        let keys = config(&[(Action::Jump, &["W"]), (Action::MoveUp, &["Up"])]);
+        assert!(ActionMap::from_config(&keys, &BindingsConfig::new()).is_ok());
+    }
+
+    #[test]
+    fn bind_refuses_keys_of_other_actions() {
+        let mut map = ActionMap::default();
+
+        assert!(matches!(map.bind(Action::Jump, Keycode::W), Err(BindError::Conflict(Keycode::W, Action::MoveUp))));
+        assert!(matches!(map.bind_button(Action::Jump, Button::B), Err(BindError::ButtonConflict(Button::B, Action::Cancel))));
+        assert!(!map.is_dirty());
+
+        assert_eq!(map.bind_replacing(Action::Jump, Keycode::W), Some(Action::MoveUp));
+        assert_eq!(map.action_for(Keycode::W), Some(Action::Jump));
+        assert!(map.is_dirty());
+    }
+
+    #[test]
+    fn unbinding_a_missing_key_is_not_a_change() {
+        let mut map = ActionMap::default();
+
+        map.unbind(Action::Jump, Keycode::W);
+        map.unbind_button(Action::Jump, Button::B);
+        assert!(!map.is_dirty());
+
+        map.unbind(Action::Jump, Keycode::Space);
+        assert!(map.is_dirty());
+        assert!(map.keys(Action::Jump).is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+use sdl2::rect::Rect;
+use std::{collections::HashMap, rc::Rc};
+use super::{ecs::World, events::EventBus, scheduler::EntityCallback};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlayMode {
+    Loop,
+    /// Plays forwards then backwards without repeating the end frames
+    PingPong,
+    /// Stops on the last frame
+    Once,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct AnimationFrame {
+    /// Source rect in the sprite's texture
+    pub rect: Rect,
+    /// Seconds
+    pub duration: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Clip {
+    pub frames: Vec<AnimationFrame>,
+    pub mode: PlayMode,
+}
+
+impl Clip {
+    pub fn new(frames: Vec<AnimationFrame>, mode: PlayMode) -> Self {
+        Self { frames, mode }
+    }
+
+    /// `count` frames of the same size laid out left to right from (x, y), wrapping after
+    /// `columns`, all shown for `duration` seconds
+    pub fn from_grid(x: i32, y: i32, w: u32, h: u32, count: u32, columns: u32, duration: f64, mode: PlayMode) -> Self {
+        let columns = columns.max(1);
+        let frames = (0..count)
+            .map(|i| AnimationFrame {
+                rect: Rect::new(x + ((i % columns) * w) as i32, y + ((i / columns) * h) as i32, w, h),
+                duration,
+            })
+            .collect();
+
+        Self { frames, mode }
+    }
+}
+
+/// Named clips for one sprite sheet, shared between every entity using the sheet
+#[derive(Debug, Clone, Default)]
+pub struct Animation {
+    clips: HashMap<String, Clip>,
+}
+
+impl Animation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_clip(mut self, name: &str, clip: Clip) -> Self {
+        self.add_clip(name, clip);
+        self
+    }
+
+    pub fn add_clip(&mut self, name: &str, clip: Clip) {
+        self.clips.insert(name.to_string(), clip);
+    }
+
+    pub fn clip(&self, name: &str) -> Option<&Clip> {
+        self.clips.get(name)
+    }
+}
+
+/// Component that plays clips of an Animation on the entity's sprite
+pub struct Animator {
+    animation: Rc<Animation>,
+    clip: String,
+    frame: usize,
+    elapsed: f64,
+    reversing: bool,
+    finished: bool,
+    pub speed: f64,
+    /// Runs when a Once clip ends and each time a looping clip starts over
+    pub on_complete: Option<EntityCallback>,
+}
+
+impl Animator {
+    pub fn new(animation: Rc<Animation>, clip: &str) -> Self {
+        Self {
+            animation,
+            clip: clip.to_string(),
+            frame: 0,
+            elapsed: 0.0,
+            reversing: false,
+            finished: false,
+            speed: 1.0,
+            on_complete: None,
+        }
+    }
+
+    pub fn with_on_complete(mut self, callback: EntityCallback) -> Self {
+        self.on_complete = Some(callback);
+        self
+    }
+
+    /// Switches clip, does nothing if it is already playing so it can be called every tick
+    pub fn play(&mut self, clip: &str) {
+        if self.clip != clip {
+            self.restart(clip);
+        }
+    }
+
+    pub fn restart(&mut self, clip: &str) {
+        self.clip = clip.to_string();
+        self.frame = 0;
+        self.elapsed = 0.0;
+        self.reversing = false;
+        self.finished = false;
+    }
+
+    pub fn clip(&self) -> &str {
+        &self.clip
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn current_rect(&self) -> Option<Rect> {
+        self.animation.clip(&self.clip)?.frames.get(self.frame).map(|f| f.rect)
+    }
+
+    /// Returns true if the clip completed during this update
+    pub fn update(&mut self, dt: f64) -> bool {
+        let animation = Rc::clone(&self.animation);
+        let clip = match animation.clip(&self.clip) {
+            Some(c) if !c.frames.is_empty() => c,
+            _ => return false,
+        };
+
+        if self.finished {
+            return false;
+        }
+
+        let last = clip.frames.len() - 1;
+        let mut completed = false;
+
+        self.elapsed += dt * self.speed;
+
+        loop {
+            // Zero length frames would never let the loop end
+            let duration = clip.frames[self.frame.min(last)].duration.max(0.001);
+
+            if self.elapsed < duration {
+                break;
+            }
+
+            self.elapsed -= duration;
+
+            match clip.mode {
+                PlayMode::Once if self.frame >= last => {
+                    self.frame = last;
+                    self.elapsed = 0.0;
+                    self.finished = true;
+                    return true;
+                }
+                PlayMode::Once => self.frame += 1,
+                PlayMode::Loop => {
+                    if self.frame >= last {
+                        self.frame = 0;
+                        completed = true;
+                    } else {
+                        self.frame += 1;
+                    }
+                }
+                PlayMode::PingPong if last == 0 => completed = true,
+                PlayMode::PingPong => {
+                    if self.reversing {
+                        self.frame -= 1;
+                        if self.frame == 0 {
+                            self.reversing = false;
+                            completed = true;
+                        }
+                    } else {
+                        self.frame += 1;
+                        if self.frame == last {
+                            self.reversing = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        completed
+    }
+}
+
+/// Advances every Animator and points its sprite at the current frame
+pub fn animate(world: &mut World<'_>, events: &mut EventBus, dt: f64) {
+    let mut completed = vec![];
+
+    for entity in world.with::<Animator>() {
+        let (rect, callback) = match world.get_mut::<Animator>(entity) {
+            Some(animator) => {
+                let done = animator.update(dt);
+                (animator.current_rect(), animator.on_complete.filter(|_| done))
+            }
+            None => continue,
+        };
+
+        if let (Some(rect), Some(sprite)) = (rect, world.sprites.get_mut(entity)) {
+            sprite.src_rect = Some(rect);
+        }
+
+        if let Some(callback) = callback {
+            completed.push((entity, callback));
+        }
+    }
+
+    // After the loop so callbacks can freely change animators or despawn
+    for (entity, callback) in completed {
+        callback(world, events, entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ecs::EntityHandle;
+
+    fn animator(mode: PlayMode, frames: u32) -> Animator {
+        let animation = Animation::new().with_clip("clip", Clip::from_grid(0, 0, 8, 8, frames, frames, 0.1, mode));
+        Animator::new(Rc::new(animation), "clip")
+    }
+
+    /// Frame shown after each of `ticks` updates of a frame's length, and whether the clip
+    /// completed on that update
+    fn step(animator: &mut Animator, ticks: usize) -> Vec<(usize, bool)> {
+        (0..ticks).map(|_| {
+            let done = animator.update(0.1);
+            (animator.frame(), done)
+        }).collect()
+    }
+
+    #[test]
+    fn loops() {
+        let mut animator = animator(PlayMode::Loop, 3);
+
+        assert_eq!(step(&mut animator, 4), vec![(1, false), (2, false), (0, true), (1, false)]);
+        assert!(!animator.is_finished());
+    }
+
+    #[test]
+    fn ping_pongs_without_repeating_the_ends() {
+        let mut animator = animator(PlayMode::PingPong, 3);
+
+        assert_eq!(step(&mut animator, 5), vec![(1, false), (2, false), (1, false), (0, true), (1, false)]);
+    }
+
+    #[test]
+    fn stops_on_the_last_frame() {
+        let mut animator = animator(PlayMode::Once, 3);
+
+        assert_eq!(step(&mut animator, 4), vec![(1, false), (2, false), (2, true), (2, false)]);
+        assert!(animator.is_finished());
+
+        animator.restart("clip");
+        assert_eq!((animator.frame(), animator.is_finished()), (0, false));
+    }
+
+    #[test]
+    fn steps_several_frames_in_a_long_update() {
+        let mut animator = animator(PlayMode::Loop, 4);
+
+        assert!(!animator.update(0.25));
+        assert_eq!(animator.frame(), 2);
+        assert_eq!(animator.current_rect(), Some(Rect::new(16, 0, 8, 8)));
+    }
+
+    struct Completed;
+
+    fn mark(world: &mut World<'_>, _: &mut EventBus, entity: EntityHandle) {
+        world.insert(entity, Completed);
+    }
+
+    #[test]
+    fn animate_calls_on_complete_when_the_clip_ends() {
+        let (mut world, mut events) = (World::new(), EventBus::new());
+        let entity = world.spawn();
+        world.insert(entity, animator(PlayMode::Once, 2).with_on_complete(mark));
+
+        animate(&mut world, &mut events, 0.1);
+        assert!(world.get::<Completed>(entity).is_none());
+        assert_eq!(world.get::<Animator>(entity).unwrap().frame(), 1);
+
+        animate(&mut world, &mut events, 0.1);
+        assert!(world.get::<Completed>(entity).is_some());
+
+        // Finished Once clips don't complete again
+        world.remove::<Completed>(entity);
+        animate(&mut world, &mut events, 0.1);
+        assert!(world.get::<Completed>(entity).is_none());
+    }
+}
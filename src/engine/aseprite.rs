@@ -0,0 +1,634 @@
+use flate2::read::ZlibDecoder;
+use sdl2::{
+    pixels::PixelFormatEnum,
+    rect::Rect,
+    render::{BlendMode, Texture, TextureCreator},
+    video::WindowContext,
+};
+use std::{fs, io::Read, path::Path};
+use super::animation::{Animation, AnimationFrame, Clip, PlayMode};
+
+// Loader for .ase/.aseprite files, following
+// https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md
+
+const HEADER_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+const HEADER_LAYER_OPACITY: u32 = 1;
+
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+const CHUNK_SLICE: u16 = 0x2022;
+
+struct Reader<'d> {
+    data: &'d [u8],
+    pos: usize,
+}
+
+impl<'d> Reader<'d> {
+    fn new(data: &'d [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'d [u8], String> {
+        let end = self.pos.checked_add(n).filter(|e| *e <= self.data.len()).ok_or("Unexpected end of Aseprite data")?;
+        let ret = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(ret)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), String> {
+        self.bytes(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        self.u16().map(|v| v as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        self.u32().map(|v| v as i32)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorDepth {
+    Rgba,
+    Grayscale,
+    Indexed,
+}
+
+impl ColorDepth {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            ColorDepth::Rgba => 4,
+            ColorDepth::Grayscale => 2,
+            ColorDepth::Indexed => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AseLayer {
+    pub name: String,
+    pub visible: bool,
+    /// Group layers only hold other layers
+    pub is_group: bool,
+    pub child_level: u16,
+    pub opacity: u8,
+    /// Only normal blending is supported, other modes are drawn as normal
+    pub blend_mode: u16,
+}
+
+/// Image data of one layer in one frame, already decoded to RGBA
+#[derive(Debug, Clone)]
+pub struct AseCel {
+    pub layer: usize,
+    pub x: i32,
+    pub y: i32,
+    pub opacity: u8,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AseFrame {
+    /// Milliseconds
+    pub duration: u32,
+    pub cels: Vec<AseCel>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TagDirection {
+    Forward,
+    Reverse,
+    PingPong,
+    PingPongReverse,
+}
+
+#[derive(Debug, Clone)]
+pub struct AseTag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    pub direction: TagDirection,
+    /// 0 repeats forever
+    pub repeat: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct AseSliceKey {
+    pub frame: usize,
+    pub rect: Rect,
+    /// Nine-slice centre, relative to `rect`
+    pub center: Option<Rect>,
+    /// Relative to `rect`
+    pub pivot: Option<(i32, i32)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AseSlice {
+    pub name: String,
+    pub keys: Vec<AseSliceKey>,
+}
+
+impl AseSlice {
+    /// The key in effect on a frame, keys last until the next one starts
+    pub fn key_for(&self, frame: usize) -> Option<&AseSliceKey> {
+        self.keys.iter().filter(|k| k.frame <= frame).last()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AsepriteFile {
+    pub width: u32,
+    pub height: u32,
+    pub depth: ColorDepth,
+    pub layers: Vec<AseLayer>,
+    pub frames: Vec<AseFrame>,
+    pub tags: Vec<AseTag>,
+    pub slices: Vec<AseSlice>,
+}
+
+fn decode_pixels(raw: &[u8], depth: ColorDepth, palette: &[[u8; 4]], transparent_index: u8) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(raw.len() / depth.bytes_per_pixel() * 4);
+
+    for px in raw.chunks_exact(depth.bytes_per_pixel()) {
+        match depth {
+            ColorDepth::Rgba => ret.extend_from_slice(px),
+            ColorDepth::Grayscale => ret.extend_from_slice(&[px[0], px[0], px[0], px[1]]),
+            ColorDepth::Indexed if px[0] == transparent_index => ret.extend_from_slice(&[0, 0, 0, 0]),
+            ColorDepth::Indexed => ret.extend_from_slice(palette.get(px[0] as usize).unwrap_or(&[0, 0, 0, 0])),
+        }
+    }
+
+    ret
+}
+
+impl AsepriteFile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let data = fs::read(path.as_ref()).map_err(|e| format!("{}: {}", path.as_ref().display(), e))?;
+        Self::parse(&data).map_err(|e| format!("{}: {}", path.as_ref().display(), e))
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let mut r = Reader::new(data);
+
+        r.u32()?;
+        if r.u16()? != HEADER_MAGIC {
+            return Err("Not an Aseprite file".into());
+        }
+
+        let frame_count = r.u16()? as usize;
+        let width = r.u16()? as u32;
+        let height = r.u16()? as u32;
+        let depth = match r.u16()? {
+            32 => ColorDepth::Rgba,
+            16 => ColorDepth::Grayscale,
+            8 => ColorDepth::Indexed,
+            d => return Err(format!("Unsupported color depth {}", d)),
+        };
+        let header_flags = r.u32()?;
+        r.skip(2 + 4 + 4)?;
+        let transparent_index = r.u8()?;
+        r.skip(128 - 29)?;
+
+        let mut ret = Self {
+            width,
+            height,
+            depth,
+            layers: vec![],
+            frames: vec![],
+            tags: vec![],
+            slices: vec![],
+        };
+
+        let mut palette: Vec<[u8; 4]> = vec![];
+        // Indexed cels are decoded once the whole file is read, the palette can come after them
+        let mut raw_cels: Vec<(usize, AseCel)> = vec![];
+
+        for frame_idx in 0..frame_count {
+            let frame_start = r.pos;
+            let frame_size = r.u32()? as usize;
+
+            if r.u16()? != FRAME_MAGIC {
+                return Err(format!("Bad magic number in frame {}", frame_idx));
+            }
+
+            let old_chunks = r.u16()? as usize;
+            let duration = r.u16()? as u32;
+            r.skip(2)?;
+            let new_chunks = r.u32()? as usize;
+            let chunks = if new_chunks == 0 { old_chunks } else { new_chunks };
+
+            ret.frames.push(AseFrame { duration, cels: vec![] });
+
+            for _ in 0..chunks {
+                let chunk_start = r.pos;
+                let chunk_size = r.u32()? as usize;
+                let chunk_type = r.u16()?;
+                let mut c = Reader::new(r.bytes(chunk_size.checked_sub(6).ok_or("Bad chunk size")?)?);
+
+                match chunk_type {
+                    CHUNK_OLD_PALETTE if palette.is_empty() => {
+                        let packets = c.u16()?;
+                        let mut idx = 0usize;
+
+                        for _ in 0..packets {
+                            idx += c.u8()? as usize;
+                            let count = match c.u8()? { 0 => 256, n => n as usize };
+
+                            for _ in 0..count {
+                                let rgb = c.bytes(3)?;
+                                if palette.len() <= idx {
+                                    palette.resize(idx + 1, [0, 0, 0, 255]);
+                                }
+                                palette[idx] = [rgb[0], rgb[1], rgb[2], 255];
+                                idx += 1;
+                            }
+                        }
+                    }
+                    CHUNK_PALETTE => {
+                        let size = c.u32()? as usize;
+                        let first = c.u32()? as usize;
+                        let last = c.u32()? as usize;
+                        c.skip(8)?;
+
+                        palette.resize(size.max(palette.len()), [0, 0, 0, 255]);
+
+                        for idx in first..=last {
+                            let flags = c.u16()?;
+                            let rgba = c.bytes(4)?;
+                            if idx < palette.len() {
+                                palette[idx] = [rgba[0], rgba[1], rgba[2], rgba[3]];
+                            }
+                            if flags & 1 != 0 {
+                                c.string()?;
+                            }
+                        }
+                    }
+                    CHUNK_LAYER => {
+                        let flags = c.u16()?;
+                        let layer_type = c.u16()?;
+                        let child_level = c.u16()?;
+                        c.skip(4)?;
+                        let blend_mode = c.u16()?;
+                        // Only valid when the header says so, older files leave it at 0
+                        let opacity = match c.u8()? {
+                            _ if header_flags & HEADER_LAYER_OPACITY == 0 => 255,
+                            o => o,
+                        };
+                        c.skip(3)?;
+                        let name = c.string()?;
+
+                        ret.layers.push(AseLayer {
+                            name,
+                            visible: flags & 1 != 0,
+                            is_group: layer_type == 1,
+                            child_level,
+                            opacity,
+                            blend_mode,
+                        });
+                    }
+                    CHUNK_CEL => {
+                        let layer = c.u16()? as usize;
+                        let x = c.i16()? as i32;
+                        let y = c.i16()? as i32;
+                        let opacity = c.u8()?;
+                        let cel_type = c.u16()?;
+                        c.skip(7)?;
+
+                        match cel_type {
+                            0 | 2 => {
+                                let w = c.u16()? as u32;
+                                let h = c.u16()? as u32;
+                                let len = (w * h) as usize * depth.bytes_per_pixel();
+
+                                let raw = if cel_type == 0 {
+                                    c.bytes(len)?.to_vec()
+                                } else {
+                                    let mut out = Vec::with_capacity(len);
+                                    ZlibDecoder::new(&c.data[c.pos..])
+                                        .read_to_end(&mut out)
+                                        .map_err(|e| format!("Bad cel data in frame {}: {}", frame_idx, e))?;
+                                    out
+                                };
+
+                                if raw.len() < len {
+                                    return Err(format!("Cel in frame {} is missing pixels", frame_idx));
+                                }
+
+                                raw_cels.push((frame_idx, AseCel { layer, x, y, opacity, width: w, height: h, pixels: raw[..len].to_vec() }));
+                            }
+                            1 => {
+                                let linked = c.u16()? as usize;
+                                let source = raw_cels
+                                    .iter()
+                                    .find(|(f, cel)| *f == linked && cel.layer == layer)
+                                    .map(|(_, cel)| cel.clone());
+
+                                if let Some(mut cel) = source {
+                                    cel.x = x;
+                                    cel.y = y;
+                                    cel.opacity = opacity;
+                                    raw_cels.push((frame_idx, cel));
+                                }
+                            }
+                            // Tilemap cels need the tileset chunks, not supported
+                            _ => {}
+                        }
+                    }
+                    CHUNK_TAGS => {
+                        let count = c.u16()?;
+                        c.skip(8)?;
+
+                        for _ in 0..count {
+                            let from = c.u16()? as usize;
+                            let to = c.u16()? as usize;
+                            let direction = match c.u8()? {
+                                1 => TagDirection::Reverse,
+                                2 => TagDirection::PingPong,
+                                3 => TagDirection::PingPongReverse,
+                                _ => TagDirection::Forward,
+                            };
+                            let repeat = c.u16()?;
+                            c.skip(6 + 3 + 1)?;
+                            let name = c.string()?;
+
+                            ret.tags.push(AseTag { name, from, to, direction, repeat });
+                        }
+                    }
+                    CHUNK_SLICE => {
+                        let key_count = c.u32()?;
+                        let flags = c.u32()?;
+                        c.skip(4)?;
+                        let name = c.string()?;
+                        let mut keys = vec![];
+
+                        for _ in 0..key_count {
+                            let frame = c.u32()? as usize;
+                            let rect = Rect::new(c.i32()?, c.i32()?, c.u32()?, c.u32()?);
+                            let center = if flags & 1 != 0 {
+                                Some(Rect::new(c.i32()?, c.i32()?, c.u32()?, c.u32()?))
+                            } else {
+                                None
+                            };
+                            let pivot = if flags & 2 != 0 { Some((c.i32()?, c.i32()?)) } else { None };
+
+                            keys.push(AseSliceKey { frame, rect, center, pivot });
+                        }
+
+                        ret.slices.push(AseSlice { name, keys });
+                    }
+                    _ => {}
+                }
+
+                r.pos = chunk_start + chunk_size;
+            }
+
+            r.pos = frame_start + frame_size;
+        }
+
+        for (frame_idx, mut cel) in raw_cels {
+            cel.pixels = decode_pixels(&cel.pixels, depth, &palette, transparent_index);
+            ret.frames[frame_idx].cels.push(cel);
+        }
+
+        Ok(ret)
+    }
+
+    /// A layer is drawn if it and every group above it is visible
+    fn visible_layers(&self) -> Vec<bool> {
+        let mut ret = vec![];
+        let mut parents: Vec<bool> = vec![];
+
+        for layer in self.layers.iter() {
+            parents.truncate(layer.child_level as usize);
+            let visible = layer.visible && parents.iter().all(|v| *v);
+            ret.push(visible && !layer.is_group);
+            parents.push(visible);
+        }
+
+        ret
+    }
+
+    /// Flattens the visible layers of a frame into width * height RGBA pixels
+    pub fn frame_pixels(&self, frame: usize) -> Vec<u8> {
+        let mut out = vec![0u8; (self.width * self.height * 4) as usize];
+        let visible = self.visible_layers();
+
+        let mut cels: Vec<&AseCel> = self.frames[frame].cels.iter().collect();
+        cels.sort_by_key(|c| c.layer);
+
+        for cel in cels {
+            if !visible.get(cel.layer).copied().unwrap_or(false) {
+                continue;
+            }
+
+            let opacity = cel.opacity as u32 * self.layers[cel.layer].opacity as u32 / 255;
+
+            for cy in 0..cel.height as i32 {
+                for cx in 0..cel.width as i32 {
+                    let (x, y) = (cel.x + cx, cel.y + cy);
+
+                    if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+                        continue;
+                    }
+
+                    let src = &cel.pixels[((cy as u32 * cel.width + cx as u32) * 4) as usize..][..4];
+                    let dst = &mut out[((y as u32 * self.width + x as u32) * 4) as usize..][..4];
+
+                    // Normal "over" blending on straight alpha
+                    let sa = src[3] as u32 * opacity / 255;
+                    let da = dst[3] as u32;
+                    let oa = sa + da * (255 - sa) / 255;
+
+                    if oa == 0 {
+                        continue;
+                    }
+
+                    for i in 0..3 {
+                        dst[i] = ((src[i] as u32 * sa + dst[i] as u32 * da * (255 - sa) / 255) / oa) as u8;
+                    }
+                    dst[3] = oa as u8;
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Every frame side by side, frame n at x = n * width
+    pub fn sheet_pixels(&self) -> (Vec<u8>, u32, u32) {
+        let sheet_width = self.width * self.frames.len() as u32;
+        let mut out = vec![0u8; (sheet_width * self.height * 4) as usize];
+
+        for frame in 0..self.frames.len() {
+            let pixels = self.frame_pixels(frame);
+            let row = (self.width * 4) as usize;
+
+            for y in 0..self.height as usize {
+                let dst = (y * sheet_width as usize + frame * self.width as usize) * 4;
+                out[dst..dst + row].copy_from_slice(&pixels[y * row..(y + 1) * row]);
+            }
+        }
+
+        (out, sheet_width, self.height)
+    }
+
+    pub fn frame_rect(&self, frame: usize) -> Rect {
+        Rect::new((frame as u32 * self.width) as i32, 0, self.width, self.height)
+    }
+
+    /// One clip per tag keyed by the tag name, plus "default" playing every frame unless a tag
+    /// already uses that name. Rects point into the sheet from sheet_pixels().
+    pub fn animation(&self) -> Animation {
+        let frame = |i: usize| AnimationFrame {
+            rect: self.frame_rect(i),
+            duration: self.frames[i].duration as f64 / 1000.0,
+        };
+
+        let mut ret = Animation::new();
+
+        if !self.tags.iter().any(|t| t.name == "default") {
+            ret.add_clip("default", Clip::new((0..self.frames.len()).map(frame).collect(), PlayMode::Loop));
+        }
+
+        for tag in self.tags.iter() {
+            let to = tag.to.min(self.frames.len().saturating_sub(1));
+            let mut frames: Vec<AnimationFrame> = (tag.from..=to).map(frame).collect();
+
+            if tag.direction == TagDirection::Reverse || tag.direction == TagDirection::PingPongReverse {
+                frames.reverse();
+            }
+
+            let mode = match (tag.direction, tag.repeat) {
+                (TagDirection::PingPong, _) | (TagDirection::PingPongReverse, _) => PlayMode::PingPong,
+                (_, 1) => PlayMode::Once,
+                _ => PlayMode::Loop,
+            };
+
+            ret.add_clip(&tag.name, Clip::new(frames, mode));
+        }
+
+        ret
+    }
+
+    pub fn slice(&self, name: &str) -> Option<&AseSlice> {
+        self.slices.iter().find(|s| s.name == name)
+    }
+
+    pub fn to_texture<'a>(&self, texture_creator: &'a TextureCreator<WindowContext>) -> Result<Texture<'a>, String> {
+        let (pixels, w, h) = self.sheet_pixels();
+        let mut texture = texture_creator
+            .create_texture_static(PixelFormatEnum::RGBA32, w, h)
+            .map_err(|e| e.to_string())?;
+
+        texture.update(None, &pixels, (w * 4) as usize).map_err(|e| e.to_string())?;
+        texture.set_blend_mode(BlendMode::Blend);
+        Ok(texture)
+    }
+}
+
+/// Loads an Aseprite file as a sprite sheet texture, see AsepriteFile::sheet_pixels()
+pub fn load_texture<'a, P: AsRef<Path>>(texture_creator: &'a TextureCreator<WindowContext>, path: P) -> Result<Texture<'a>, String> {
+    AsepriteFile::load(path)?.to_texture(texture_creator)
+}
+
+/// The sprite sheet plus its clips keyed by tag name, ready for an Animator
+pub fn load_animated<'a, P: AsRef<Path>>(texture_creator: &'a TextureCreator<WindowContext>, path: P) -> Result<(Texture<'a>, Animation), String> {
+    let file = AsepriteFile::load(path)?;
+    Ok((file.to_texture(texture_creator)?, file.animation()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(name: &str) -> AsepriteFile {
+        AsepriteFile::load(Path::new(env!("CARGO_MANIFEST_DIR")).join("assets").join(name)).unwrap()
+    }
+
+    fn pixel(file: &AsepriteFile, x: u32, y: u32) -> [u8; 4] {
+        let pixels = file.frame_pixels(0);
+        let i = ((y * file.width + x) * 4) as usize;
+        [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+    }
+
+    #[test]
+    fn parses_the_rects() {
+        for (name, color) in [
+            ("white_rect.ase", [255, 255, 255, 255]),
+            ("blue_rect.ase", [91, 110, 225, 255]),
+            ("red_rect.ase", [217, 87, 99, 255]),
+            ("green_rect.ase", [106, 190, 48, 255]),
+        ].iter() {
+            let file = asset(name);
+
+            assert_eq!((file.width, file.height, file.frames.len()), (20, 20, 1), "{}", name);
+            assert_eq!(pixel(&file, 0, 0), *color, "{}", name);
+            assert_eq!(pixel(&file, 19, 19), *color, "{}", name);
+        }
+    }
+
+    #[test]
+    fn parses_the_panel_slice() {
+        let file = asset("panel.ase");
+
+        assert_eq!((file.width, file.height), (16, 16));
+        assert_eq!(pixel(&file, 0, 0), [34, 32, 52, 255]);
+        assert_eq!(pixel(&file, 8, 8), [63, 63, 116, 255]);
+
+        let key = file.slice("panel").and_then(|s| s.key_for(0)).unwrap();
+        assert_eq!(key.rect, Rect::new(0, 0, 16, 16));
+        assert_eq!(key.center, Some(Rect::new(4, 4, 8, 8)));
+    }
+
+    #[test]
+    fn parses_the_tilemap() {
+        let file = asset("tilemap.aseprite");
+
+        assert_eq!((file.width, file.height), (200, 200));
+        assert_eq!(pixel(&file, 0, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel(&file, 100, 100), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ignores_layer_opacity_without_the_header_flag() {
+        let mut data = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/panel.ase")).unwrap();
+
+        // The layer chunk is the first one in the first frame, its opacity is 12 bytes in
+        let opacity = 128 + 16 + 6 + 12;
+        assert_eq!(u16::from_le_bytes([data[128 + 16 + 4], data[128 + 16 + 5]]), CHUNK_LAYER);
+        data[opacity] = 0;
+
+        let file = AsepriteFile::parse(&data).unwrap();
+        assert_eq!(pixel(&file, 0, 0), [0, 0, 0, 0]);
+
+        data[14] &= !(HEADER_LAYER_OPACITY as u8);
+        let file = AsepriteFile::parse(&data).unwrap();
+        assert_eq!(pixel(&file, 0, 0), [34, 32, 52, 255]);
+    }
+}
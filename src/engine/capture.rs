@@ -0,0 +1,222 @@
+use sdl2::{
+    image::{LoadSurface, SaveSurface},
+    pixels::PixelFormatEnum,
+    render::Canvas,
+    surface::Surface,
+    video::Window,
+};
+use std::path::{Path, PathBuf};
+
+const FORMAT: PixelFormatEnum = PixelFormatEnum::RGBA32;
+const BYTES_PER_PIXEL: usize = 4;
+
+/// RGBA pixels read back from a canvas or loaded from an image
+#[derive(Clone)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+pub struct FrameDiff {
+    pub differing_pixels: usize,
+    pub max_delta: u8,
+    pub diff: Frame,
+}
+
+impl Frame {
+    /// Reads what was drawn in the canvas viewport, in output pixels, without any letterbox bars
+    /// around it. Must be called before canvas.present().
+    pub fn capture(canvas: &Canvas<Window>) -> Result<Self, String> {
+        // The viewport is in logical units, read_pixels(None) reads it in output pixels
+        let viewport = canvas.viewport();
+        let (scale_x, scale_y) = canvas.scale();
+        let width = (viewport.width() as f32 * scale_x) as u32;
+        let height = (viewport.height() as f32 * scale_y) as u32;
+        let pixels = canvas.read_pixels(None, FORMAT)?;
+
+        if pixels.len() != width as usize * height as usize * BYTES_PER_PIXEL {
+            return Err(format!("Read {} bytes from a {}x{} viewport", pixels.len(), width, height));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let surface = Surface::from_file(path)?.convert_format(FORMAT)?;
+        let width = surface.width();
+        let height = surface.height();
+        let pitch = surface.pitch() as usize;
+        let row_len = width as usize * BYTES_PER_PIXEL;
+
+        let pixels = surface.with_lock(|data| {
+            let mut pixels = Vec::with_capacity(row_len * height as usize);
+            for y in 0..height as usize {
+                pixels.extend_from_slice(&data[y * pitch..y * pitch + row_len]);
+            }
+            pixels
+        });
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let mut pixels = self.pixels.clone();
+        let pitch = self.width * BYTES_PER_PIXEL as u32;
+        let surface = Surface::from_data(&mut pixels, self.width, self.height, pitch, FORMAT)?;
+        surface.save(path)
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y * self.width + x) as usize * BYTES_PER_PIXEL;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    /// Compares each channel of each pixel. Pixels that differ by more than `tolerance` are
+    /// drawn red in the diff image, matching pixels are drawn as a faded copy of this frame.
+    pub fn diff(&self, other: &Frame, tolerance: u8) -> Result<FrameDiff, String> {
+        if self.width != other.width || self.height != other.height {
+            return Err(format!(
+                "Frame size {}x{} does not match {}x{}",
+                self.width, self.height, other.width, other.height
+            ));
+        }
+
+        let mut differing_pixels = 0;
+        let mut max_delta = 0;
+        let mut diff_pixels = Vec::with_capacity(self.pixels.len());
+
+        for (a, b) in self.pixels.chunks(BYTES_PER_PIXEL).zip(other.pixels.chunks(BYTES_PER_PIXEL)) {
+            let delta = a.iter()
+                .zip(b.iter())
+                .map(|(x, y)| (*x as i16 - *y as i16).abs() as u8)
+                .max()
+                .unwrap_or(0);
+
+            max_delta = max_delta.max(delta);
+
+            if delta > tolerance {
+                differing_pixels += 1;
+                diff_pixels.extend_from_slice(&[255, 0, 0, 255]);
+            } else {
+                let grey = ((a[0] as u16 + a[1] as u16 + a[2] as u16) / 12) as u8;
+                diff_pixels.extend_from_slice(&[grey, grey, grey, 255]);
+            }
+        }
+
+        Ok(FrameDiff {
+            differing_pixels,
+            max_delta,
+            diff: Frame {
+                width: self.width,
+                height: self.height,
+                pixels: diff_pixels,
+            },
+        })
+    }
+
+    /// Compares against a stored golden image. On mismatch the actual frame and a diff image are
+    /// written next to the golden as <name>.actual.png and <name>.diff.png.
+    /// Set UPDATE_GOLDEN=1 to overwrite the golden with this frame instead.
+    pub fn compare_to_golden<P: AsRef<Path>>(&self, golden_path: P, tolerance: u8) -> Result<(), String> {
+        let golden_path = golden_path.as_ref();
+
+        if std::env::var("UPDATE_GOLDEN").map_or(false, |v| v == "1") {
+            return self.save_png(golden_path);
+        }
+
+        if !golden_path.exists() {
+            self.save_png(sibling_path(golden_path, "actual"))?;
+            return Err(format!(
+                "Golden image {} does not exist, run with UPDATE_GOLDEN=1 to create it",
+                golden_path.display()
+            ));
+        }
+
+        let golden = Frame::load_png(golden_path)?;
+
+        let result = self.diff(&golden, tolerance).and_then(|d| {
+            if d.differing_pixels == 0 {
+                return Ok(());
+            }
+
+            d.diff.save_png(sibling_path(golden_path, "diff"))?;
+            Err(format!(
+                "{} pixels differ from {} (max channel delta {}, tolerance {})",
+                d.differing_pixels,
+                golden_path.display(),
+                d.max_delta,
+                tolerance
+            ))
+        });
+
+        if result.is_err() {
+            self.save_png(sibling_path(golden_path, "actual"))?;
+        }
+
+        result
+    }
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+    path.with_file_name(format!("{}.{}.png", stem, suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, rgba: [u8; 4]) -> Frame {
+        Frame {
+            width,
+            height,
+            pixels: rgba.iter().copied().cycle().take((width * height) as usize * BYTES_PER_PIXEL).collect(),
+        }
+    }
+
+    fn set_pixel(frame: &mut Frame, x: u32, y: u32, rgba: [u8; 4]) {
+        let i = (y * frame.width + x) as usize * BYTES_PER_PIXEL;
+        frame.pixels[i..i + BYTES_PER_PIXEL].copy_from_slice(&rgba);
+    }
+
+    #[test]
+    fn ignores_differences_within_tolerance() {
+        let a = solid(4, 3, [120, 60, 30, 255]);
+        let mut b = a.clone();
+        set_pixel(&mut b, 1, 1, [123, 58, 30, 255]);
+
+        let diff = a.diff(&b, 3).unwrap();
+        assert_eq!((diff.differing_pixels, diff.max_delta), (0, 3));
+
+        let diff = a.diff(&b, 2).unwrap();
+        assert_eq!((diff.differing_pixels, diff.max_delta), (1, 3));
+    }
+
+    #[test]
+    fn marks_differing_pixels_red() {
+        let a = solid(3, 2, [60, 90, 150, 255]);
+        let mut b = a.clone();
+        set_pixel(&mut b, 2, 1, [60, 90, 150, 0]);
+
+        let diff = a.diff(&b, 0).unwrap();
+        assert_eq!((diff.diff.width, diff.diff.height), (3, 2));
+        assert_eq!(diff.diff.pixel(2, 1), [255, 0, 0, 255]);
+        // Matching pixels are a darkened grey copy of the first frame
+        assert_eq!(diff.diff.pixel(0, 0), [25, 25, 25, 255]);
+        assert_eq!(diff.differing_pixels, 1);
+    }
+
+    #[test]
+    fn rejects_frames_of_another_size() {
+        assert!(solid(4, 3, [0; 4]).diff(&solid(3, 4, [0; 4]), 255).is_err());
+    }
+}
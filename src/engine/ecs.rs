@@ -0,0 +1,649 @@
+use nphysics2d::object::DefaultBodyHandle;
+use sdl2::{render::Canvas, video::Window};
+use std::{any::{Any, TypeId}, collections::{BTreeSet, HashMap}};
+use crate::geometry::{SimpleRect, Vec2};
+use super::{sprite::Sprite, text::FontAtlas, rendering::{Camera, Drawable}, events::EventBus, transform::Transform};
+
+/// Stable reference to a spawned entity. The generation is bumped whenever a slot is freed, so a
+/// handle to a despawned entity never resolves to whatever reused its slot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntityHandle {
+    index: u32,
+    generation: u32,
+}
+
+/// Interned tag name, get one from World::tag()
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TagId(u32);
+
+/// Components of one type, indexed by entity slot
+pub struct Storage<T> {
+    items: Vec<Option<(EntityHandle, T)>>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Self { items: vec![] }
+    }
+}
+
+impl<T> Storage<T> {
+    pub fn insert(&mut self, entity: EntityHandle, component: T) -> Option<T> {
+        let idx = entity.index as usize;
+
+        if idx >= self.items.len() {
+            self.items.resize_with(idx + 1, || None);
+        }
+
+        self.items[idx].replace((entity, component)).map(|(_, c)| c)
+    }
+
+    pub fn remove(&mut self, entity: EntityHandle) -> Option<T> {
+        match self.items.get_mut(entity.index as usize) {
+            Some(slot) if slot.as_ref().map_or(false, |(e, _)| *e == entity) => slot.take().map(|(_, c)| c),
+            _ => None
+        }
+    }
+
+    pub fn contains(&self, entity: EntityHandle) -> bool {
+        self.get(entity).is_some()
+    }
+
+    pub fn get(&self, entity: EntityHandle) -> Option<&T> {
+        match self.items.get(entity.index as usize) {
+            Some(Some((e, c))) if *e == entity => Some(c),
+            _ => None
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: EntityHandle) -> Option<&mut T> {
+        match self.items.get_mut(entity.index as usize) {
+            Some(Some((e, c))) if *e == entity => Some(c),
+            _ => None
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityHandle, &T)> {
+        self.items.iter().filter_map(|i| i.as_ref().map(|(e, c)| (*e, c)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityHandle, &mut T)> {
+        self.items.iter_mut().filter_map(|i| i.as_mut().map(|(e, c)| (*e, c)))
+    }
+
+    pub fn entities(&self) -> Vec<EntityHandle> {
+        self.iter().map(|(e, _)| e).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+/// Lets the world drop a despawned entity's components without knowing their types
+trait AnyStorage {
+    fn remove_entity(&mut self, entity: EntityHandle);
+    fn clear(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn remove_entity(&mut self, entity: EntityHandle) {
+        self.remove(entity);
+    }
+
+    fn clear(&mut self) {
+        Storage::clear(self);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Entities and their components.
+///
+/// Components that borrow assets (sprites) live in their own fields since they can't be stored
+/// by TypeId, anything else goes through insert()/get()/query().
+pub struct World<'a> {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free_slots: Vec<u32>,
+    pending_despawns: Vec<EntityHandle>,
+    /// Local transforms, relative to the parent if there is one
+    pub transforms: Storage<Transform>,
+    /// World space transforms, written by resolve_transforms()
+    pub global_transforms: Storage<Transform>,
+    parents: Storage<EntityHandle>,
+    children: Storage<Vec<EntityHandle>>,
+    pub sprites: Storage<Sprite<'a>>,
+    tags: Storage<Vec<TagId>>,
+    tag_names: Vec<String>,
+    tag_ids: HashMap<String, TagId>,
+    tag_index: HashMap<TagId, BTreeSet<EntityHandle>>,
+    pub bodies: Storage<DefaultBodyHandle>,
+    components: HashMap<TypeId, Box<dyn AnyStorage>>,
+}
+
+impl<'a> World<'a> {
+    pub fn new() -> Self {
+        Self {
+            generations: vec![],
+            alive: vec![],
+            free_slots: vec![],
+            pending_despawns: vec![],
+            transforms: Storage::default(),
+            global_transforms: Storage::default(),
+            parents: Storage::default(),
+            children: Storage::default(),
+            sprites: Storage::default(),
+            tags: Storage::default(),
+            tag_names: vec![],
+            tag_ids: HashMap::new(),
+            tag_index: HashMap::new(),
+            bodies: Storage::default(),
+            components: HashMap::new(),
+        }
+    }
+
+    /// Entities spawned while a system runs are picked up by the next query, not the current one
+    pub fn spawn(&mut self) -> EntityHandle {
+        match self.free_slots.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                EntityHandle { index, generation: self.generations[index as usize] }
+            }
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                EntityHandle { index: self.generations.len() as u32 - 1, generation: 0 }
+            }
+        }
+    }
+
+    /// The entity, its children and their components stay around until maintain() runs at the
+    /// end of the tick
+    pub fn despawn(&mut self, entity: EntityHandle) {
+        if self.is_alive(entity) && !self.pending_despawns.contains(&entity) {
+            self.pending_despawns.push(entity);
+
+            for child in self.children(entity).to_vec() {
+                self.despawn(child);
+            }
+        }
+    }
+
+    pub fn is_alive(&self, entity: EntityHandle) -> bool {
+        let idx = entity.index as usize;
+        idx < self.alive.len() && self.alive[idx] && self.generations[idx] == entity.generation
+    }
+
+    pub fn entities(&self) -> Vec<EntityHandle> {
+        (0..self.alive.len())
+            .filter(|i| self.alive[*i])
+            .map(|i| EntityHandle { index: i as u32, generation: self.generations[i] })
+            .collect()
+    }
+
+    fn free(&mut self, entity: EntityHandle) {
+        self.unlink(entity);
+
+        for child in self.children.remove(entity).unwrap_or_default() {
+            self.parents.remove(child);
+        }
+
+        self.transforms.remove(entity);
+        self.global_transforms.remove(entity);
+        self.sprites.remove(entity);
+        self.bodies.remove(entity);
+
+        for tag in self.tags.remove(entity).unwrap_or_default() {
+            if let Some(tagged) = self.tag_index.get_mut(&tag) {
+                tagged.remove(&entity);
+            }
+        }
+
+        for storage in self.components.values_mut() {
+            storage.remove_entity(entity);
+        }
+
+        let idx = entity.index as usize;
+        self.alive[idx] = false;
+        self.generations[idx] = self.generations[idx].wrapping_add(1);
+        self.free_slots.push(entity.index);
+    }
+
+    /// Frees everything despawned this tick
+    pub fn maintain(&mut self) {
+        let pending: Vec<EntityHandle> = self.pending_despawns.drain(..).collect();
+
+        for entity in pending {
+            if self.is_alive(entity) {
+                self.free(entity);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for entity in self.entities() {
+            self.free(entity);
+        }
+
+        self.pending_despawns.clear();
+    }
+
+    pub fn parent(&self, entity: EntityHandle) -> Option<EntityHandle> {
+        self.parents.get(entity).copied()
+    }
+
+    pub fn children(&self, entity: EntityHandle) -> &[EntityHandle] {
+        self.children.get(entity).map(|c| c.as_slice()).unwrap_or(&[])
+    }
+
+    fn unlink(&mut self, child: EntityHandle) {
+        if let Some(parent) = self.parents.remove(child) {
+            if let Some(siblings) = self.children.get_mut(parent) {
+                siblings.retain(|c| *c != child);
+            }
+        }
+    }
+
+    fn is_ancestor(&self, ancestor: EntityHandle, entity: EntityHandle) -> bool {
+        let mut current = Some(entity);
+
+        while let Some(e) = current {
+            if e == ancestor {
+                return true;
+            }
+            current = self.parent(e);
+        }
+
+        false
+    }
+
+    /// Makes `child` follow `parent`, its local transform is kept and now counts from the parent
+    pub fn attach(&mut self, child: EntityHandle, parent: EntityHandle) -> Result<(), String> {
+        if !self.is_alive(child) || !self.is_alive(parent) {
+            return Err("Can't attach a despawned entity".into());
+        }
+
+        if self.is_ancestor(child, parent) {
+            return Err(format!("Attaching {:?} to {:?} would make a cycle", child, parent));
+        }
+
+        self.unlink(child);
+        self.parents.insert(child, parent);
+
+        match self.children.get_mut(parent) {
+            Some(children) => children.push(child),
+            None => { self.children.insert(parent, vec![child]); }
+        }
+
+        Ok(())
+    }
+
+    /// Moves `child` under another parent, or to the root with None, without moving it on screen
+    pub fn reparent(&mut self, child: EntityHandle, parent: Option<EntityHandle>) -> Result<(), String> {
+        let world = self.global_transform(child).unwrap_or_default();
+
+        match parent {
+            Some(p) => {
+                self.attach(child, p)?;
+                let parent_world = self.global_transform(p).unwrap_or_default();
+                self.transforms.insert(child, parent_world.relative(&world));
+            }
+            None => {
+                self.unlink(child);
+                self.transforms.insert(child, world);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn detach(&mut self, child: EntityHandle) {
+        self.reparent(child, None).ok();
+    }
+
+    /// Walks up the parents instead of using the last resolve_transforms() result, so it is
+    /// correct straight after changing a transform
+    pub fn global_transform(&self, entity: EntityHandle) -> Option<Transform> {
+        let mut ret = *self.transforms.get(entity)?;
+        let mut current = self.parent(entity);
+
+        while let Some(parent) = current {
+            ret = self.transforms.get(parent).copied().unwrap_or_default().combine(&ret);
+            current = self.parent(parent);
+        }
+
+        Some(ret)
+    }
+
+    pub fn storage<T: 'static>(&self) -> Option<&Storage<T>> {
+        self.components
+            .get(&TypeId::of::<T>())
+            .and_then(|s| s.as_any().downcast_ref::<Storage<T>>())
+    }
+
+    /// Like storage_mut() but doesn't create the storage, for lookups and removals
+    fn existing_storage_mut<T: 'static>(&mut self) -> Option<&mut Storage<T>> {
+        self.components
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|s| s.as_any_mut().downcast_mut::<Storage<T>>())
+    }
+
+    pub fn storage_mut<T: 'static>(&mut self) -> &mut Storage<T> {
+        self.components
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Storage::<T>::default()))
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .expect("Component storage registered under the wrong type")
+    }
+
+    pub fn insert<T: 'static>(&mut self, entity: EntityHandle, component: T) {
+        if self.is_alive(entity) {
+            self.storage_mut::<T>().insert(entity, component);
+        }
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: EntityHandle) -> Option<T> {
+        self.existing_storage_mut::<T>().and_then(|s| s.remove(entity))
+    }
+
+    pub fn has<T: 'static>(&self, entity: EntityHandle) -> bool {
+        self.storage::<T>().map_or(false, |s| s.contains(entity))
+    }
+
+    pub fn get<T: 'static>(&self, entity: EntityHandle) -> Option<&T> {
+        self.storage::<T>().and_then(|s| s.get(entity))
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: EntityHandle) -> Option<&mut T> {
+        self.existing_storage_mut::<T>().and_then(|s| s.get_mut(entity))
+    }
+
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (EntityHandle, &T)> {
+        self.storage::<T>().into_iter().flat_map(|s| s.iter())
+    }
+
+    /// Snapshot of the entities that have a T, for systems that need to touch other components
+    /// or spawn and despawn while walking the results
+    pub fn with<T: 'static>(&self) -> Vec<EntityHandle> {
+        self.storage::<T>().map(|s| s.entities()).unwrap_or_default()
+    }
+
+    /// Interns a tag name, look tags up once and keep the id around for per-frame queries
+    pub fn tag(&mut self, name: &str) -> TagId {
+        if let Some(id) = self.tag_ids.get(name) {
+            return *id;
+        }
+
+        let id = TagId(self.tag_names.len() as u32);
+        self.tag_names.push(name.to_string());
+        self.tag_ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Like tag() but doesn't intern unknown names
+    pub fn find_tag(&self, name: &str) -> Option<TagId> {
+        self.tag_ids.get(name).copied()
+    }
+
+    pub fn tag_name(&self, tag: TagId) -> &str {
+        &self.tag_names[tag.0 as usize]
+    }
+
+    pub fn add_tag(&mut self, entity: EntityHandle, name: &str) -> TagId {
+        let tag = self.tag(name);
+
+        if !self.is_alive(entity) || self.has_tag(entity, tag) {
+            return tag;
+        }
+
+        match self.tags.get_mut(entity) {
+            Some(tags) => tags.push(tag),
+            None => { self.tags.insert(entity, vec![tag]); }
+        }
+
+        self.tag_index.entry(tag).or_insert_with(BTreeSet::new).insert(entity);
+        tag
+    }
+
+    pub fn remove_tag(&mut self, entity: EntityHandle, tag: TagId) {
+        if let Some(tags) = self.tags.get_mut(entity) {
+            tags.retain(|t| *t != tag);
+        }
+
+        if let Some(tagged) = self.tag_index.get_mut(&tag) {
+            tagged.remove(&entity);
+        }
+    }
+
+    pub fn has_tag(&self, entity: EntityHandle, tag: TagId) -> bool {
+        self.tags.get(entity).map_or(false, |tags| tags.contains(&tag))
+    }
+
+    pub fn tags(&self, entity: EntityHandle) -> &[TagId] {
+        self.tags.get(entity).map(|t| t.as_slice()).unwrap_or(&[])
+    }
+
+    /// Entities with the tag, in spawn slot order
+    pub fn with_tag(&self, tag: TagId) -> impl Iterator<Item = EntityHandle> + '_ {
+        self.tag_index.get(&tag).into_iter().flat_map(|tagged| tagged.iter().copied())
+    }
+
+    /// Entities with every one of the tags, walks the smallest tag set and checks the rest
+    pub fn with_tags<'q>(&'q self, tags: &'q [TagId]) -> impl Iterator<Item = EntityHandle> + 'q {
+        let smallest = if tags.iter().all(|t| self.tag_index.contains_key(t)) {
+            tags.iter().filter_map(|t| self.tag_index.get(t)).min_by_key(|tagged| tagged.len())
+        } else {
+            None
+        };
+
+        // Borrows the tag storage rather than self so the iterator doesn't capture the world's lifetime
+        let entity_tags = &self.tags;
+
+        smallest
+            .into_iter()
+            .flat_map(|tagged| tagged.iter().copied())
+            .filter(move |e| entity_tags.get(*e).map_or(false, |et| tags.iter().all(|t| et.contains(t))))
+    }
+
+    /// Entities whose world position is inside the region, as of the last resolve_transforms()
+    pub fn in_region(&self, region: SimpleRect) -> impl Iterator<Item = (EntityHandle, &Transform)> + '_ {
+        self.global_transforms.iter().filter(move |(_, t)| region.contains(t.pos))
+    }
+
+    pub fn draw_sprites<'f>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'f>, camera: &Camera) {
+        for (_, sprite) in self.sprites.iter_mut() {
+            if sprite.visible() {
+                sprite.draw(canvas, font_atlas, camera);
+            }
+        }
+    }
+}
+
+pub type System<'a> = fn(&mut World<'a>, &mut EventBus, f64);
+
+/// Systems run in the order they were added, then events are dispatched and finally despawns
+/// are applied
+pub struct Schedule<'a> {
+    systems: Vec<(String, System<'a>)>,
+}
+
+impl<'a> Schedule<'a> {
+    pub fn new() -> Self {
+        Self { systems: vec![] }
+    }
+
+    pub fn with_system(mut self, name: &str, system: System<'a>) -> Self {
+        self.add_system(name, system);
+        self
+    }
+
+    pub fn add_system(&mut self, name: &str, system: System<'a>) {
+        self.systems.push((name.to_string(), system));
+    }
+
+    pub fn remove_system(&mut self, name: &str) {
+        self.systems.retain(|(n, _)| n != name);
+    }
+
+    pub fn run(&mut self, world: &mut World<'a>, events: &mut EventBus, dt: f64) {
+        for (_, system) in self.systems.iter() {
+            system(world, events, dt);
+        }
+
+        events.dispatch(world);
+        world.maintain();
+    }
+}
+
+/// Works out the world transform of everything with a transform, parents before children
+pub fn resolve_transforms(world: &mut World<'_>, _events: &mut EventBus, _dt: f64) {
+    world.global_transforms.clear();
+
+    let mut stack: Vec<(EntityHandle, Transform)> = world
+        .transforms
+        .iter()
+        .filter(|(e, _)| world.parent(*e).is_none())
+        .map(|(e, t)| (e, *t))
+        .collect();
+
+    stack.reverse();
+
+    while let Some((entity, global)) = stack.pop() {
+        world.global_transforms.insert(entity, global);
+
+        for child in world.children(entity).iter().rev() {
+            if let Some(local) = world.transforms.get(*child) {
+                stack.push((*child, global.combine(local)));
+            }
+        }
+    }
+}
+
+/// Moves every sprite to its entity's world position, the entity's rotation and scale are applied
+/// on top of the sprite's own
+pub fn sync_sprites(world: &mut World<'_>, _events: &mut EventBus, _dt: f64) {
+    for (entity, global) in world.global_transforms.iter() {
+        if let Some(sprite) = world.sprites.get_mut(entity) {
+            sprite.set_pos(global.pos.x, global.pos.y);
+            sprite.entity_angle = global.rotation;
+            sprite.entity_scale = global.scale;
+        }
+    }
+}
+
+/// Copies rigid body positions from the physics world, bodies are expected to be on root entities
+pub fn sync_bodies(world: &mut World<'_>, physics: &super::physics::PhysicsWorld) {
+    for (entity, body) in world.bodies.iter() {
+        if let (Some(pos), Some(t)) = (physics.body_position(*body), world.transforms.get_mut(entity)) {
+            t.pos = pos;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(i32);
+
+    #[test]
+    fn reused_slots_get_a_new_generation() {
+        let mut world = World::new();
+        let first = world.spawn();
+        world.despawn(first);
+        world.maintain();
+
+        let second = world.spawn();
+        assert_eq!(second.index, first.index);
+        assert_ne!(second.generation, first.generation);
+        assert!(!world.is_alive(first));
+        assert!(world.is_alive(second));
+    }
+
+    #[test]
+    fn stale_handles_resolve_to_nothing() {
+        let mut world = World::new();
+        let old = world.spawn();
+        world.insert(old, Health(3));
+        world.transforms.insert(old, Transform::at(1.0, 2.0));
+
+        // Despawns only apply on maintain()
+        world.despawn(old);
+        assert_eq!(world.get::<Health>(old), Some(&Health(3)));
+        world.maintain();
+
+        let new = world.spawn();
+        world.insert(new, Health(5));
+
+        assert_eq!(world.get::<Health>(old), None);
+        assert_eq!(world.get_mut::<Health>(old), None);
+        assert_eq!(world.remove::<Health>(old), None);
+        assert!(world.transforms.get(old).is_none());
+        assert_eq!(world.get::<Health>(new), Some(&Health(5)));
+
+        world.insert(old, Health(9));
+        assert_eq!(world.get::<Health>(new), Some(&Health(5)));
+    }
+
+    #[test]
+    fn lookups_dont_create_storages() {
+        let mut world = World::new();
+        let entity = world.spawn();
+
+        assert!(world.get_mut::<Health>(entity).is_none());
+        assert!(world.remove::<Health>(entity).is_none());
+        assert!(world.storage::<Health>().is_none());
+    }
+
+    #[test]
+    fn despawning_a_parent_despawns_its_children() {
+        let mut world = World::new();
+        let (parent, child) = (world.spawn(), world.spawn());
+        world.attach(child, parent).unwrap();
+        assert!(world.attach(parent, child).is_err());
+
+        world.despawn(parent);
+        world.maintain();
+        assert!(!world.is_alive(child));
+    }
+
+    #[test]
+    fn indexes_tags() {
+        let mut world = World::new();
+        let (a, b, c) = (world.spawn(), world.spawn(), world.spawn());
+        let enemy = world.add_tag(a, "enemy");
+        world.add_tag(b, "enemy");
+        let flying = world.add_tag(b, "flying");
+        world.add_tag(c, "flying");
+
+        // Adding a tag twice doesn't list the entity twice
+        world.add_tag(a, "enemy");
+
+        assert_eq!(world.with_tag(enemy).collect::<Vec<_>>(), vec![a, b]);
+        assert_eq!(world.with_tags(&[enemy, flying]).collect::<Vec<_>>(), vec![b]);
+        assert_eq!(world.tags(a), &[enemy]);
+        assert_eq!(world.find_tag("boss"), None);
+
+        let boss = world.tag("boss");
+        assert_eq!(world.with_tags(&[enemy, boss]).count(), 0);
+
+        world.remove_tag(b, enemy);
+        assert_eq!(world.with_tag(enemy).collect::<Vec<_>>(), vec![a]);
+
+        world.despawn(a);
+        world.maintain();
+        assert_eq!(world.with_tag(enemy).count(), 0);
+        assert_eq!(world.with_tag(flying).collect::<Vec<_>>(), vec![b, c]);
+    }
+}
@@ -0,0 +1,242 @@
+use std::{any::{Any, TypeId}, collections::BTreeMap, rc::Rc};
+use super::ecs::{EntityHandle, World};
+
+#[derive(Debug, Clone)]
+pub struct Event<T> {
+    /// None for broadcasts
+    pub target: Option<EntityHandle>,
+    pub data: T,
+}
+
+pub type Handler<T> = fn(&mut World<'_>, &mut EventBus, &Event<T>);
+
+/// Runs a channel's handlers on its delivered events without borrowing the bus
+type Delivery = Box<dyn FnOnce(&mut World<'_>, &mut EventBus)>;
+
+struct Channel<T> {
+    pending: Vec<Event<T>>,
+    /// Shared with the running Delivery so handlers can still read() them
+    delivered: Rc<Vec<Event<T>>>,
+    handlers: Vec<Handler<T>>,
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self {
+            pending: vec![],
+            delivered: Rc::new(vec![]),
+            handlers: vec![],
+        }
+    }
+}
+
+trait AnyChannel {
+    /// Makes the pending events the delivered ones
+    fn flush(&mut self);
+    fn delivery(&self) -> Delivery;
+    fn clear(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyChannel for Channel<T> {
+    fn flush(&mut self) {
+        self.delivered = Rc::new(std::mem::take(&mut self.pending));
+    }
+
+    fn delivery(&self) -> Delivery {
+        let events = Rc::clone(&self.delivered);
+        let handlers = self.handlers.clone();
+
+        Box::new(move |world, bus| {
+            for event in events.iter() {
+                for handler in handlers.iter() {
+                    handler(world, bus, event);
+                }
+            }
+        })
+    }
+
+    fn clear(&mut self) {
+        self.pending.clear();
+        self.delivered = Rc::new(vec![]);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Typed events between systems and entities.
+///
+/// Events published during a tick are delivered all at once by dispatch(), which the schedule
+/// runs after every system and before despawns are applied, so targets are still alive when
+/// handlers run. Anything published by a handler goes out with the next dispatch. Delivered
+/// events can also be polled with read() until the following dispatch.
+pub struct EventBus {
+    // BTreeMap so event types are delivered in the same order every run, replays depend on it
+    channels: BTreeMap<TypeId, Box<dyn AnyChannel>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            channels: BTreeMap::new(),
+        }
+    }
+
+    fn channel<T: 'static>(&self) -> Option<&Channel<T>> {
+        self.channels
+            .get(&TypeId::of::<T>())
+            .and_then(|c| c.as_any().downcast_ref::<Channel<T>>())
+    }
+
+    fn channel_mut<T: 'static>(&mut self) -> &mut Channel<T> {
+        self.channels
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Channel::<T>::default()))
+            .as_any_mut()
+            .downcast_mut::<Channel<T>>()
+            .expect("Event channel registered under the wrong type")
+    }
+
+    pub fn publish<T: 'static>(&mut self, data: T) {
+        self.channel_mut::<T>().pending.push(Event { target: None, data });
+    }
+
+    pub fn publish_to<T: 'static>(&mut self, target: EntityHandle, data: T) {
+        self.channel_mut::<T>().pending.push(Event { target: Some(target), data });
+    }
+
+    /// Handlers run in the order they subscribed
+    pub fn subscribe<T: 'static>(&mut self, handler: Handler<T>) {
+        self.channel_mut::<T>().handlers.push(handler);
+    }
+
+    /// Everything delivered by the last dispatch
+    pub fn read<T: 'static>(&self) -> impl Iterator<Item = &Event<T>> {
+        self.channel::<T>().into_iter().flat_map(|c| c.delivered.iter())
+    }
+
+    /// Events from the last dispatch that were aimed at the entity
+    pub fn read_for<T: 'static>(&self, entity: EntityHandle) -> impl Iterator<Item = &T> {
+        self.read::<T>().filter(move |e| e.target == Some(entity)).map(|e| &e.data)
+    }
+
+    /// Every channel is flushed before any handler runs, so handlers can read() all of this
+    /// dispatch's events. Handlers subscribed by a handler start with the next dispatch.
+    pub fn dispatch(&mut self, world: &mut World<'_>) {
+        for channel in self.channels.values_mut() {
+            channel.flush();
+        }
+
+        let deliveries: Vec<Delivery> = self.channels.values().map(|c| c.delivery()).collect();
+
+        for deliver in deliveries {
+            deliver(world, self);
+        }
+    }
+
+    /// Drops queued and delivered events, handlers stay subscribed
+    pub fn clear(&mut self) {
+        for channel in self.channels.values_mut() {
+            channel.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Hit(u32);
+
+    /// Published by handlers to record what they saw
+    #[derive(Debug, PartialEq)]
+    struct Seen(&'static str, u32);
+
+    fn first(_: &mut World<'_>, bus: &mut EventBus, event: &Event<Hit>) {
+        bus.publish(Seen("first", event.data.0));
+    }
+
+    fn second(_: &mut World<'_>, bus: &mut EventBus, event: &Event<Hit>) {
+        bus.publish(Seen("second", event.data.0));
+    }
+
+    fn count_hits(_: &mut World<'_>, bus: &mut EventBus, _: &Event<Hit>) {
+        let hits = bus.read::<Hit>().count() as u32;
+        bus.publish(Seen("read", hits));
+    }
+
+    fn seen(bus: &EventBus) -> Vec<(&'static str, u32)> {
+        bus.read::<Seen>().map(|e| (e.data.0, e.data.1)).collect()
+    }
+
+    #[test]
+    fn delivers_in_publish_and_subscribe_order() {
+        let (mut bus, mut world) = (EventBus::new(), World::new());
+        bus.subscribe(first);
+        bus.subscribe(second);
+
+        bus.publish(Hit(1));
+        bus.publish(Hit(2));
+        bus.dispatch(&mut world);
+
+        assert_eq!(bus.read::<Hit>().map(|e| e.data.0).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(seen(&bus).is_empty());
+
+        // What the handlers published arrives with the next dispatch
+        bus.dispatch(&mut world);
+        assert_eq!(seen(&bus), vec![("first", 1), ("second", 1), ("first", 2), ("second", 2)]);
+        assert_eq!(bus.read::<Hit>().count(), 0);
+    }
+
+    #[test]
+    fn handlers_can_read_the_bus() {
+        let (mut bus, mut world) = (EventBus::new(), World::new());
+        bus.subscribe(count_hits);
+
+        bus.publish(Hit(1));
+        bus.publish(Hit(2));
+        bus.dispatch(&mut world);
+        bus.dispatch(&mut world);
+
+        assert_eq!(seen(&bus), vec![("read", 2), ("read", 2)]);
+    }
+
+    #[test]
+    fn read_for_only_gives_the_target_events() {
+        let (mut bus, mut world) = (EventBus::new(), World::new());
+        let (a, b) = (world.spawn(), world.spawn());
+
+        bus.publish_to(a, Hit(1));
+        bus.publish_to(b, Hit(2));
+        bus.publish(Hit(3));
+        bus.dispatch(&mut world);
+
+        assert_eq!(bus.read_for::<Hit>(a).collect::<Vec<_>>(), vec![&Hit(1)]);
+        assert_eq!(bus.read_for::<Hit>(b).collect::<Vec<_>>(), vec![&Hit(2)]);
+        assert_eq!(bus.read::<Hit>().count(), 3);
+    }
+
+    #[test]
+    fn clear_keeps_handlers() {
+        let (mut bus, mut world) = (EventBus::new(), World::new());
+        bus.subscribe(first);
+
+        bus.publish(Hit(1));
+        bus.clear();
+        bus.dispatch(&mut world);
+        assert_eq!(bus.read::<Hit>().count(), 0);
+
+        bus.publish(Hit(2));
+        bus.dispatch(&mut world);
+        bus.dispatch(&mut world);
+        assert_eq!(seen(&bus), vec![("first", 2)]);
+    }
+}
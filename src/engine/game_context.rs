@@ -1,68 +1,184 @@
-use crate::{game::{assets::Assets, player::Player}, game_object_types::GameObjectType};
-use super::rendering::RenderList;
-
-pub trait GameObject {
-    // type TProps;
-    // fn init(&mut self, props: Self::TProps);
-    fn tags(&self) -> Vec<String>;
-    fn update(&mut self);
-    fn set_pos(&mut self, x: f32, y: f32);
-}
-
-type GameObjectRef = Box<dyn GameObject>;
+use sdl2::{render::Canvas, video::Window};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use crate::{constants::*, game::assets::Assets, geometry::Vec2};
+use super::{
+    animation,
+    ecs::{self, EntityHandle, Schedule, World},
+    events::EventBus,
+    prefabs::{PrefabRegistry, PropValues},
+    rendering::{Camera, Drawable, Layer, RenderList},
+    rng::Rng,
+    save::{self, SaveData, SAVE_VERSION},
+    scheduler::Scheduler,
+    text::FontAtlas,
+    tiles,
+};
 
 pub struct GameContext<'a> {
-    game_objects: Vec<Box<dyn GameObject>>,
-    assets: Assets<'a>,
-    render_list: RenderList
+    pub world: World<'a>,
+    pub schedule: Schedule<'a>,
+    pub events: EventBus,
+    pub scheduler: Scheduler,
+    pub camera: Camera,
+    pub prefabs: PrefabRegistry<'a>,
+    assets: &'a Assets<'a>,
+    pub render_list: RenderList<'a>,
+    pub rng: Rng,
+    level: Option<String>,
+    /// Player progress that isn't tied to an entity, saved as-is
+    pub progress: BTreeMap<String, Value>
 }
 
 pub struct Level {
-    game_objects: Vec<GameObjectConfig>
+    pub name: String,
+    pub game_objects: Vec<GameObjectConfig>
 }
 
 pub struct GameObjectConfig {
-    game_object_type: GameObjectType,
-    x: f32,
-    y: f32,
+    pub prefab: String,
+    pub props: PropValues,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Level {
+    /// Builds a level from every visible object layer of the map, the object type names the prefab
+    /// and custom properties override its props
+    pub fn from_tiled(name: &str, map: &tiled::Map) -> Result<Self, String> {
+        let mut game_objects = vec![];
+
+        for group in map.object_groups.iter().filter(|g| g.visible) {
+            for object in group.objects.iter() {
+                let prefab = tiles::object_type(object);
+
+                if prefab.is_empty() {
+                    return Err(format!("Object {} in layer '{}' has no type", object.id, group.name));
+                }
+
+                let pos = tiles::object_pos(object);
+
+                game_objects.push(GameObjectConfig {
+                    prefab,
+                    props: tiles::object_props(object),
+                    x: pos.x as f32,
+                    y: pos.y as f32,
+                });
+            }
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            game_objects
+        })
+    }
 }
 
 impl<'a> GameContext<'a> {
-    pub fn new(assets: Assets<'a>) -> Self {
+    /// Pass the seed from InputManager::seed() so replays roll the same numbers
+    pub fn new(assets: &'a Assets<'a>, seed: u64) -> Self {
         Self {
-            game_objects: vec![],
+            world: World::new(),
+            schedule: Schedule::new()
+                .with_system("animate", animation::animate)
+                .with_system("resolve_transforms", ecs::resolve_transforms)
+                .with_system("sync_sprites", ecs::sync_sprites),
+            events: EventBus::new(),
+            scheduler: Scheduler::new(),
+            camera: Camera::new(SCREEN_WIDTH, SCREEN_HEIGHT),
+            prefabs: PrefabRegistry::new(),
             assets,
-            render_list: RenderList::new()
+            render_list: RenderList::new(),
+            rng: Rng::new(seed),
+            level: None,
+            progress: BTreeMap::new()
         }
     }
 
-    pub fn get_objects_by_tag(&mut self, tag: String) -> Vec<&mut GameObjectRef> {
-        let mut ret = vec![];
+    pub fn with_prefabs(mut self, prefabs: PrefabRegistry<'a>) -> Self {
+        self.prefabs = prefabs;
+        self
+    }
 
-        for go in self.game_objects.iter_mut() {
-            let tags = go.tags();
-            if tags.contains(&tag) {
-                ret.push(go);
-            }
-        }
+    pub fn spawn(&mut self, prefab: &str, overrides: &PropValues, x: f64, y: f64) -> Result<EntityHandle, String> {
+        self.prefabs.spawn(prefab, &mut self.world, self.assets, overrides, Vec2::new(x, y))
+    }
+
+    pub fn get_objects_by_tag(&self, tag: &str) -> impl Iterator<Item = EntityHandle> + '_ {
+        self.world.find_tag(tag).map(|t| self.world.with_tag(t)).into_iter().flatten()
+    }
 
-        return ret;
+    pub fn update(&mut self, dt: f64) {
+        self.scheduler.update(&mut self.world, &mut self.events, dt);
+        self.schedule.run(&mut self.world, &mut self.events, dt);
+        self.camera.update(&self.world, dt);
     }
 
-    pub fn spawn(&mut self, object: GameObjectRef) {
-        self.game_objects.push(object);
+    pub fn render<'f>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'f>) {
+        // Sprites share the world layer's ordering with whatever else is in it
+        let mut sprites: Vec<&mut dyn Drawable> = self.world.sprites.iter_mut().map(|(_, s)| s as &mut dyn Drawable).collect();
+        self.render_list.draw_with(canvas, font_atlas, &self.camera, Layer::World, &mut sprites);
     }
 
-    pub fn load_level(&mut self, level: &Level) {
-        self.game_objects.clear();
+    /// Checks every object against the prefab registry before clearing the current level
+    pub fn load_level(&mut self, level: &Level) -> Result<(), String> {
+        for config in level.game_objects.iter() {
+            self.prefabs.resolve(&config.prefab, &config.props)?;
+        }
+
+        self.world.clear();
+        self.events.clear();
+        self.scheduler.clear();
         self.render_list.clear();
 
         for config in level.game_objects.iter() {
-            match config.game_object_type {
-                GameObjectType::Player(props) => {
-                    self.game_objects.push(Box::new(Player::new(props, &self.assets)));
-                }
-            }
+            self.spawn(&config.prefab, &config.props, config.x as f64, config.y as f64)?;
         }
+
+        self.level = Some(level.name.clone());
+        Ok(())
+    }
+
+    pub fn level(&self) -> Option<&str> {
+        self.level.as_deref()
     }
-}
\ No newline at end of file
+
+    /// Only entities spawned from a prefab are saved, see save::save_entities
+    pub fn save_state(&self) -> SaveData {
+        SaveData {
+            version: SAVE_VERSION,
+            level: self.level.clone(),
+            rng_state: self.rng.state(),
+            progress: self.progress.clone(),
+            entities: save::save_entities(&self.world),
+        }
+    }
+
+    /// Replaces the entities with the saved ones. Nothing is touched if a saved prefab no longer
+    /// exists or the parent links are broken. The render list isn't made of entities and is kept,
+    /// so is the level; load the saved level first if it can differ. The camera stops following
+    /// its old target, which no longer exists.
+    pub fn restore_state(&mut self, save: &SaveData) -> Result<(), String> {
+        for saved in save.entities.iter() {
+            self.prefabs.resolve(&saved.prefab, &saved.props)?;
+        }
+        save::check_parents(&save.entities)?;
+
+        self.world.clear();
+        self.events.clear();
+        self.scheduler.clear();
+        self.camera.follow(None);
+
+        let prefabs = &self.prefabs;
+        let assets = self.assets;
+
+        save::restore_entities(&mut self.world, &save.entities, |world, saved| {
+            prefabs.spawn(&saved.prefab, world, assets, &saved.props, Vec2::new(saved.x, saved.y))
+        })?;
+
+        self.level = save.level.clone();
+        self.rng = Rng::new(save.rng_state);
+        self.progress = save.progress.clone();
+        Ok(())
+    }
+}
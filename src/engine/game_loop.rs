@@ -0,0 +1,111 @@
+use sdl2::{video::Window, render::Canvas};
+use std::time::Instant;
+use super::{input::InputManager, scene::{Scene, SceneCommand}, text::FontAtlas};
+
+pub struct GameLoop {
+    tick_dt: f64,
+    max_ticks_per_frame: u32,
+    accumulator: f64,
+    last_frame: Option<Instant>,
+    tick: u128,
+}
+
+impl GameLoop {
+    pub fn new(tick_rate: f64) -> Self {
+        Self {
+            tick_dt: 1.0 / tick_rate,
+            max_ticks_per_frame: 5,
+            accumulator: 0.0,
+            last_frame: None,
+            tick: 0,
+        }
+    }
+
+    pub fn with_max_ticks_per_frame(mut self, max_ticks_per_frame: u32) -> Self {
+        self.max_ticks_per_frame = max_ticks_per_frame.max(1);
+        self
+    }
+
+    /// Seconds of simulated time per tick
+    pub fn tick_dt(&self) -> f64 {
+        self.tick_dt
+    }
+
+    /// Number of ticks simulated so far
+    pub fn ticks(&self) -> u128 {
+        self.tick
+    }
+
+    /// Simulated time in milliseconds, derived from the tick count so it is the same on every machine
+    pub fn t(&self) -> u128 {
+        (self.tick as f64 * self.tick_dt * 1000.0) as u128
+    }
+
+    /// How far (0.0 - 1.0) the current frame sits between the last tick and the next one
+    pub fn alpha(&self) -> f64 {
+        (self.accumulator / self.tick_dt).max(0.0).min(1.0)
+    }
+
+    /// Adds the wall clock time since the last frame to the accumulator and returns how many ticks
+    /// should be run. Catch-up is capped at max_ticks_per_frame, any time beyond that is dropped so
+    /// a long hitch does not turn into a spiral of ever longer frames.
+    pub fn advance(&mut self) -> u32 {
+        let now = Instant::now();
+
+        let elapsed = match self.last_frame {
+            Some(last) => now.duration_since(last).as_secs_f64(),
+            None => self.tick_dt,
+        };
+
+        self.last_frame = Some(now);
+        self.accumulator += elapsed;
+
+        let mut ticks = 0;
+        while self.accumulator >= self.tick_dt && ticks < self.max_ticks_per_frame {
+            self.accumulator -= self.tick_dt;
+            ticks += 1;
+        }
+
+        if self.accumulator >= self.tick_dt {
+            self.accumulator %= self.tick_dt;
+        }
+
+        ticks
+    }
+
+    /// Runs a single fixed update of the scene, `players` holds one InputManager per player slot
+    pub fn tick<'a>(&mut self, scene: &mut dyn Scene<'a>, players: &mut [InputManager]) -> SceneCommand<'a> {
+        let inputs = players
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(player, input_manager)| {
+                input_manager.collect_game_inputs().into_iter().map(move |input| (player, input))
+            })
+            .collect();
+        let command = scene.update(inputs, self.t(), self.tick_dt);
+        self.tick += 1;
+        command
+    }
+
+    /// Runs the ticks that are due and renders the scene. Returns false once the scene asks to quit.
+    pub fn run_frame<'a>(&mut self, scene: &mut dyn Scene<'a>, players: &mut [InputManager], canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'_>) -> bool {
+        for _ in 0..self.advance() {
+            if let SceneCommand::Quit = self.tick(scene, players) {
+                return false;
+            }
+        }
+
+        scene.render(canvas, font_atlas, self.alpha());
+        true
+    }
+
+    /// Runs exactly one tick per frame regardless of the wall clock, so headless runs are reproducible
+    pub fn run_fixed_frame<'a>(&mut self, scene: &mut dyn Scene<'a>, players: &mut [InputManager], canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'_>) -> bool {
+        if let SceneCommand::Quit = self.tick(scene, players) {
+            return false;
+        }
+
+        scene.render(canvas, font_atlas, 0.0);
+        true
+    }
+}
@@ -0,0 +1,162 @@
+use sdl2::{
+    controller::{Axis, Button, GameController},
+    event::Event,
+    GameControllerSubsystem,
+};
+use std::collections::HashMap;
+
+/// A gamepad event once it has been routed to a player
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GamepadInput {
+    ButtonDown(Button),
+    ButtonUp(Button),
+    Axis(Axis, i16),
+    /// The player's controller was unplugged, anything it was holding is released
+    Disconnected,
+}
+
+/// Radial deadzone for analog sticks, as fractions of the full stick range
+#[derive(Debug, Copy, Clone)]
+pub struct Deadzone {
+    pub inner: f64,
+    pub outer: f64,
+}
+
+impl Default for Deadzone {
+    fn default() -> Self {
+        Self {
+            inner: 0.2,
+            outer: 0.95,
+        }
+    }
+}
+
+impl Deadzone {
+    /// Zeroes the stick inside the inner radius and rescales the rest so output still starts at 0
+    /// and reaches 1 at the outer radius
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let mag = (x * x + y * y).sqrt();
+
+        if mag <= self.inner || mag == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let scaled = ((mag - self.inner) / (self.outer - self.inner).max(f64::EPSILON)).min(1.0);
+        (x / mag * scaled, y / mag * scaled)
+    }
+}
+
+pub fn axis_value(value: i16) -> f64 {
+    (value as f64 / i16::MAX as f64).max(-1.0)
+}
+
+/// Opens controllers as they are plugged in and assigns each one to a player slot for local co-op
+pub struct GamepadManager {
+    subsystem: GameControllerSubsystem,
+    controllers: HashMap<u32, GameController>,
+    players: Vec<Option<u32>>,
+}
+
+impl GamepadManager {
+    pub fn new(subsystem: GameControllerSubsystem, max_players: usize) -> Self {
+        Self {
+            subsystem,
+            controllers: HashMap::new(),
+            players: vec![None; max_players],
+        }
+    }
+
+    pub fn player_for(&self, instance_id: u32) -> Option<usize> {
+        self.players.iter().position(|p| *p == Some(instance_id))
+    }
+
+    pub fn controller_for(&self, player: usize) -> Option<&GameController> {
+        self.players.get(player).copied().flatten().and_then(|id| self.controllers.get(&id))
+    }
+
+    /// Moves a controller to another player slot. The controller that was there takes the moved
+    /// one's old slot, or the first free slot if the moved one had none.
+    pub fn assign(&mut self, instance_id: u32, player: usize) {
+        if player >= self.players.len() || !self.controllers.contains_key(&instance_id) {
+            return;
+        }
+
+        let previous_slot = self.player_for(instance_id);
+
+        if previous_slot == Some(player) {
+            return;
+        }
+
+        let displaced = self.players[player];
+
+        self.players[player] = Some(instance_id);
+
+        match previous_slot {
+            Some(slot) => self.players[slot] = displaced,
+            None => {
+                if let Some(slot) = self.players.iter_mut().find(|p| p.is_none()) {
+                    *slot = displaced;
+                }
+            }
+        }
+    }
+
+    fn device_added(&mut self, joystick_index: u32) -> Result<(), String> {
+        if !self.subsystem.is_game_controller(joystick_index) {
+            return Ok(());
+        }
+
+        let controller = self
+            .subsystem
+            .open(joystick_index)
+            .map_err(|e| format!("Failed to open game controller {}: {}", joystick_index, e))?;
+
+        let instance_id = controller.instance_id();
+
+        if self.controllers.contains_key(&instance_id) {
+            return Ok(());
+        }
+
+        self.controllers.insert(instance_id, controller);
+
+        if let Some(slot) = self.players.iter_mut().find(|p| p.is_none()) {
+            *slot = Some(instance_id);
+        }
+        Ok(())
+    }
+
+    fn device_removed(&mut self, instance_id: u32) -> Option<usize> {
+        self.controllers.remove(&instance_id);
+
+        let player = self.player_for(instance_id)?;
+        self.players[player] = None;
+        Some(player)
+    }
+
+    /// Handles hot-plugging and turns controller events into (player, input) pairs.
+    /// Events from controllers without a player slot are dropped. Fails if a newly plugged in
+    /// controller can't be opened.
+    pub fn handle_event(&mut self, event: &Event) -> Result<Option<(usize, GamepadInput)>, String> {
+        let input = match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                self.device_added(which)?;
+                None
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.device_removed(which).map(|player| (player, GamepadInput::Disconnected))
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                self.player_for(which).map(|player| (player, GamepadInput::ButtonDown(button)))
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                self.player_for(which).map(|player| (player, GamepadInput::ButtonUp(button)))
+            }
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                self.player_for(which).map(|player| (player, GamepadInput::Axis(axis, value)))
+            }
+            _ => None
+        };
+
+        Ok(input)
+    }
+}
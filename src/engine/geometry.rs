@@ -59,6 +59,10 @@ impl SimpleRect {
         }
     }
 
+    pub fn contains(&self, p: Vec2) -> bool {
+        p.x >= self.left() && p.x < self.right() && p.y >= self.top() && p.y < self.bottom()
+    }
+
     pub fn clamp(&mut self, min_x: f64, min_y: f64, max_x: f64, max_y: f64) {
         self.pos.x = self.pos.x.max(min_x).min(max_x - self.size.x);
         self.pos.y = self.pos.y.max(min_y).min(max_y - self.size.y);
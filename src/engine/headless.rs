@@ -0,0 +1,58 @@
+use sdl2::keyboard::Keycode;
+use super::input::InputManager;
+
+/// Scripted key events fed into the InputManager on specific frames, used when running without a window
+#[derive(Default)]
+pub struct SyntheticInput {
+    events: Vec<(u32, Keycode, bool)>,
+}
+
+impl SyntheticInput {
+    pub fn new() -> Self {
+        Self {
+            events: vec![]
+        }
+    }
+
+    pub fn key_down(&mut self, frame: u32, keycode: Keycode) {
+        self.events.push((frame, keycode, true));
+    }
+
+    pub fn key_up(&mut self, frame: u32, keycode: Keycode) {
+        self.events.push((frame, keycode, false));
+    }
+
+    /// Holds the key down for a single frame
+    pub fn press(&mut self, frame: u32, keycode: Keycode) {
+        self.key_down(frame, keycode);
+        self.key_up(frame + 1, keycode);
+    }
+
+    pub fn apply(&self, frame: u32, input_manager: &mut InputManager) {
+        for (f, keycode, down) in self.events.iter() {
+            if *f != frame {
+                continue;
+            }
+
+            if *down {
+                input_manager.process_keydown(*keycode);
+            } else {
+                input_manager.process_keyup(*keycode);
+            }
+        }
+    }
+
+    /// Parses "FRAME:KEY", e.g. "30:Down" or "120:Space". Key names are SDL key names.
+    pub fn parse_press(&mut self, s: &str) -> Result<(), String> {
+        let mut parts = s.splitn(2, ':');
+        let frame = parts.next()
+            .and_then(|f| f.parse::<u32>().ok())
+            .ok_or(format!("Invalid frame in '{}'", s))?;
+        let keycode = parts.next()
+            .and_then(Keycode::from_name)
+            .ok_or(format!("Invalid key name in '{}'", s))?;
+
+        self.press(frame, keycode);
+        Ok(())
+    }
+}
@@ -1,64 +1,407 @@
-use sdl2::{keyboard::Keycode};
+use sdl2::{controller::{Axis, Button}, keyboard::Keycode, mouse::MouseButton};
 use std::collections::HashMap;
+use super::{actions::{Action, ActionMap}, gamepad::{self, Deadzone, GamepadInput}, replay::Replay, rng};
+
+/// How far the stick has to be pushed (after the deadzone) to count as a digital direction
+const STICK_DIGITAL_THRESHOLD: f64 = 0.5;
+
+/// Mouse input in logical (canvas) coordinates
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PointerEvent {
+    Move(i32, i32),
+    Down(MouseButton, i32, i32),
+    Up(MouseButton, i32, i32),
+    Wheel(i32, i32),
+}
+
+/// Raw input as it arrives from SDL, these are what gets recorded into replays
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputEvent {
+    Key(Keycode, bool),
+    Gamepad(GamepadInput),
+    Pointer(PointerEvent),
+}
 
 pub enum GameInput {
     Move(f64, f64),
+    /// The action went down this tick
+    Pressed(Action),
+    /// The action went up this tick, with how many ticks it was held for
+    Released(Action, u64),
     Jump,
     Up,
     Down,
+    Confirm,
+    Cancel,
+    Menu,
+    Pointer(PointerEvent),
     Other(Keycode),
     None,
 }
 
+#[derive(Debug, Default, Copy, Clone)]
+struct ActionState {
+    held: bool,
+    pending_press: bool,
+    pressed_tick: Option<u64>,
+    released_tick: Option<u64>,
+}
+
 pub struct InputManager {
-    inputs: Vec<GameInput>, 
+    inputs: Vec<GameInput>,
     pub keyboard_states: HashMap<Keycode, bool>,
+    pub button_states: HashMap<Button, bool>,
+    pub mouse_states: HashMap<MouseButton, bool>,
+    pointer: (i32, i32),
+    pub actions: ActionMap,
+    pub deadzone: Deadzone,
+    stick: (f64, f64),
+    tick_events: Vec<InputEvent>,
+    recording: Option<Replay>,
+    replaying: Option<(Replay, usize)>,
+    seed: u64,
+    tick: u64,
+    action_states: HashMap<Action, ActionState>,
+    repeat_delay: u64,
+    repeat_interval: u64,
 }
 
 impl InputManager {
     pub fn new() -> Self {
         Self {
             inputs: vec![],
-            keyboard_states: HashMap::new()
+            keyboard_states: HashMap::new(),
+            button_states: HashMap::new(),
+            mouse_states: HashMap::new(),
+            pointer: (0, 0),
+            actions: ActionMap::default(),
+            deadzone: Deadzone::default(),
+            stick: (0.0, 0.0),
+            tick_events: vec![],
+            recording: None,
+            replaying: None,
+            seed: rng::seed_from_time(),
+            tick: 0,
+            action_states: HashMap::new(),
+            repeat_delay: 24,
+            repeat_interval: 6,
+        }
+    }
+
+    /// Seed for the session's Rng. Comes from the replay when one is playing.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Records the key events of every tick from now on, along with the current bindings
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Replay::new(self.seed).with_bindings(&self.actions));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Replay> {
+        self.recording.take()
+    }
+
+    /// Live key events are ignored while replaying, each tick gets the events recorded for it instead.
+    /// The bindings are swapped for the ones the replay was recorded with, if it has them.
+    pub fn start_replay(&mut self, replay: Replay) -> Result<(), String> {
+        if let Some(actions) = replay.action_map() {
+            self.actions = actions?;
+        }
+
+        self.keyboard_states.clear();
+        self.button_states.clear();
+        self.mouse_states.clear();
+        self.stick = (0.0, 0.0);
+        self.inputs.clear();
+        self.tick_events.clear();
+        self.action_states.clear();
+        self.seed = replay.seed;
+        self.replaying = Some((replay, 0));
+        Ok(())
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replaying.is_some()
+    }
+
+    pub fn is_replay_finished(&self) -> bool {
+        match &self.replaying {
+            Some((replay, tick)) => *tick >= replay.ticks.len(),
+            None => false
         }
     }
 
     pub fn process_keydown(&mut self, keycode: Keycode) {
-        self.keyboard_states.insert(keycode, true);
+        if self.replaying.is_none() {
+            self.apply_keydown(keycode);
+        }
     }
 
     pub fn process_keyup(&mut self, keycode: Keycode) {
+        if self.replaying.is_none() {
+            self.apply_keyup(keycode);
+        }
+    }
+
+    /// Input from the controller assigned to this player, see GamepadManager::handle_event
+    pub fn process_gamepad(&mut self, input: GamepadInput) {
+        if self.replaying.is_none() {
+            self.apply_gamepad(input);
+        }
+    }
+
+    /// Coordinates must already be logical, as they are on SDL mouse events
+    pub fn process_pointer(&mut self, event: PointerEvent) {
+        if self.replaying.is_none() {
+            self.apply_pointer(event);
+        }
+    }
+
+    fn apply_keydown(&mut self, keycode: Keycode) {
+        self.tick_events.push(InputEvent::Key(keycode, true));
+        self.keyboard_states.insert(keycode, true);
+
+        match self.actions.action_for(keycode) {
+            Some(action) => self.action_state_mut(action).pending_press = true,
+            None => self.inputs.push(GameInput::Other(keycode)),
+        }
+    }
+
+    fn apply_keyup(&mut self, keycode: Keycode) {
+        self.tick_events.push(InputEvent::Key(keycode, false));
         self.keyboard_states.insert(keycode, false);
+    }
+
+    fn apply_pointer(&mut self, event: PointerEvent) {
+        self.tick_events.push(InputEvent::Pointer(event));
 
-        match keycode {
-            Keycode::Space => {
-                self.inputs.push(GameInput::Jump)
+        match event {
+            PointerEvent::Move(x, y) => self.pointer = (x, y),
+            PointerEvent::Down(button, x, y) => {
+                self.pointer = (x, y);
+                self.mouse_states.insert(button, true);
             }
-            Keycode::Up | Keycode::W => {
-                self.inputs.push(GameInput::Up)
+            PointerEvent::Up(button, x, y) => {
+                self.pointer = (x, y);
+                self.mouse_states.insert(button, false);
             }
-            Keycode::Down | Keycode::S => {
-                self.inputs.push(GameInput::Down)
-            },
-            _ => {
-                self.inputs.push(GameInput::Other(keycode));
+            PointerEvent::Wheel(..) => {}
+        }
+
+        self.inputs.push(GameInput::Pointer(event));
+    }
+
+    fn apply_gamepad(&mut self, input: GamepadInput) {
+        self.tick_events.push(InputEvent::Gamepad(input));
+
+        match input {
+            GamepadInput::ButtonDown(button) => {
+                self.button_states.insert(button, true);
+                if let Some(action) = self.actions.action_for_button(button) {
+                    self.action_state_mut(action).pending_press = true;
+                }
+            }
+            GamepadInput::ButtonUp(button) => {
+                self.button_states.insert(button, false);
+            }
+            GamepadInput::Axis(Axis::LeftX, value) => self.stick.0 = gamepad::axis_value(value),
+            GamepadInput::Axis(Axis::LeftY, value) => self.stick.1 = gamepad::axis_value(value),
+            GamepadInput::Axis(..) => {}
+            GamepadInput::Disconnected => {
+                self.button_states.clear();
+                self.stick = (0.0, 0.0);
             }
         }
     }
 
+    fn replay_tick(&mut self) {
+        let events = match self.replaying.as_mut() {
+            Some((replay, tick)) => {
+                let events = replay.tick(*tick).cloned().unwrap_or_default();
+                *tick += 1;
+                events
+            }
+            None => return
+        };
+
+        for event in events {
+            match event {
+                InputEvent::Key(keycode, true) => self.apply_keydown(keycode),
+                InputEvent::Key(keycode, false) => self.apply_keyup(keycode),
+                InputEvent::Gamepad(input) => self.apply_gamepad(input),
+                InputEvent::Pointer(event) => self.apply_pointer(event),
+            }
+        }
+    }
+
+    fn action_state_mut(&mut self, action: Action) -> &mut ActionState {
+        self.action_states.entry(action).or_insert_with(ActionState::default)
+    }
+
+    fn action_state(&self, action: Action) -> ActionState {
+        self.action_states.get(&action).copied().unwrap_or_default()
+    }
+
     pub fn is_key_down(&self, keycode: Keycode) -> bool {
             self.keyboard_states.contains_key(&keycode)
         && *self.keyboard_states.get(&keycode).unwrap()
     }
 
+    /// Last known pointer position in logical coordinates
+    pub fn pointer(&self) -> (i32, i32) {
+        self.pointer
+    }
+
+    pub fn is_mouse_down(&self, button: MouseButton) -> bool {
+        *self.mouse_states.get(&button).unwrap_or(&false)
+    }
+
+    pub fn is_button_down(&self, button: Button) -> bool {
+        *self.button_states.get(&button).unwrap_or(&false)
+    }
+
+    /// Left stick position with the deadzone applied
+    pub fn stick(&self) -> (f64, f64) {
+        self.deadzone.apply(self.stick.0, self.stick.1)
+    }
+
+    pub fn is_action_down(&self, action: Action) -> bool {
+        let (sx, sy) = self.stick();
+        let stick_down = match action {
+            Action::MoveUp => sy < -STICK_DIGITAL_THRESHOLD,
+            Action::MoveDown => sy > STICK_DIGITAL_THRESHOLD,
+            Action::MoveLeft => sx < -STICK_DIGITAL_THRESHOLD,
+            Action::MoveRight => sx > STICK_DIGITAL_THRESHOLD,
+            _ => false
+        };
+
+        stick_down
+            || self.actions.keys(action).iter().any(|k| self.is_key_down(*k))
+            || self.actions.buttons(action).iter().any(|b| self.is_button_down(*b))
+    }
+
+    /// Number of ticks collected so far
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Went down this tick
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.action_state(action).pressed_tick == Some(self.tick)
+    }
+
+    /// Currently down, including the tick it was pressed on
+    pub fn is_held(&self, action: Action) -> bool {
+        self.action_state(action).held
+    }
+
+    /// Went up this tick
+    pub fn is_released(&self, action: Action) -> bool {
+        self.action_state(action).released_tick == Some(self.tick)
+    }
+
+    /// Ticks the action has been held for, 0 when it is not held. On the tick it is released this
+    /// is how long it was held.
+    pub fn held_ticks(&self, action: Action) -> u64 {
+        let state = self.action_state(action);
+
+        match state.pressed_tick {
+            Some(pressed) if state.held => self.tick - pressed,
+            Some(pressed) if state.released_tick == Some(self.tick) => self.tick - pressed,
+            _ => 0
+        }
+    }
+
+    /// True on the tick the action is pressed, then every repeat_interval ticks once it has been
+    /// held for repeat_delay ticks. For menu navigation.
+    pub fn is_repeated(&self, action: Action) -> bool {
+        if self.is_pressed(action) {
+            return true;
+        }
+
+        let held = self.held_ticks(action);
+        self.is_held(action)
+            && held >= self.repeat_delay
+            && (held - self.repeat_delay) % self.repeat_interval.max(1) == 0
+    }
+
+    pub fn set_key_repeat(&mut self, delay_ticks: u64, interval_ticks: u64) {
+        self.repeat_delay = delay_ticks;
+        self.repeat_interval = interval_ticks;
+    }
+
+    fn update_action_states(&mut self) {
+        for action in Action::ALL.iter() {
+            let down = self.is_action_down(*action);
+            let tick = self.tick;
+            let state = self.action_state_mut(*action);
+
+            // A press and release between two ticks still counts as a press
+            let pressed = !state.held && (down || state.pending_press);
+            if pressed {
+                state.pressed_tick = Some(tick);
+            }
+
+            if (state.held || pressed) && !down {
+                state.released_tick = Some(tick);
+            }
+
+            state.held = down;
+            state.pending_press = false;
+        }
+    }
+
+    /// Called once per tick
     pub fn collect_game_inputs(&mut self) -> Vec<GameInput> {
-        let mut dx: f64 = 0.0;
-        let mut dy: f64 = 0.0;
+        self.replay_tick();
 
-        if self.is_key_down(Keycode::W) || self.is_key_down(Keycode::Up) { dy = -1.0; }
-        if self.is_key_down(Keycode::A) || self.is_key_down(Keycode::Left) { dx = -1.0; }
-        if self.is_key_down(Keycode::S) || self.is_key_down(Keycode::Down) { dy = 1.0; }
-        if self.is_key_down(Keycode::D) || self.is_key_down(Keycode::Right) { dx = 1.0; }
+        let tick_events: Vec<InputEvent> = self.tick_events.drain(..).collect();
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push_tick(tick_events);
+        }
+
+        self.tick += 1;
+        self.update_action_states();
+
+        for action in Action::ALL.iter() {
+            let action = *action;
+
+            if self.is_pressed(action) {
+                self.inputs.push(GameInput::Pressed(action));
+
+                match action {
+                    Action::Jump => self.inputs.push(GameInput::Jump),
+                    Action::Confirm => self.inputs.push(GameInput::Confirm),
+                    Action::Cancel => self.inputs.push(GameInput::Cancel),
+                    Action::Menu => self.inputs.push(GameInput::Menu),
+                    _ => {}
+                }
+            }
+
+            if self.is_repeated(action) {
+                match action {
+                    Action::MoveUp => self.inputs.push(GameInput::Up),
+                    Action::MoveDown => self.inputs.push(GameInput::Down),
+                    _ => {}
+                }
+            }
+
+            if self.is_released(action) {
+                let held_ticks = self.held_ticks(action);
+                self.inputs.push(GameInput::Released(action, held_ticks));
+            }
+        }
+
+        let (mut dx, mut dy) = self.stick();
+
+        // The analog stick wins over digital directions when it is pushed
+        if dx == 0.0 && dy == 0.0 {
+            if self.is_held(Action::MoveUp) { dy = -1.0; }
+            if self.is_held(Action::MoveLeft) { dx = -1.0; }
+            if self.is_held(Action::MoveDown) { dy = 1.0; }
+            if self.is_held(Action::MoveRight) { dx = 1.0; }
+        }
 
         if dx.abs() > 0.0 || dy.abs() > 0.0 {
             let mag = (dy.powf(2.0) + dx.powf(2.0)).sqrt();
@@ -1,10 +1,25 @@
 pub mod scene;
 pub mod geometry;
 pub mod sprite;
+pub mod animation;
+pub mod aseprite;
 pub mod input;
 pub mod tiles;
+pub mod transform;
 pub mod ui;
 pub mod text;
 pub mod physics;
+pub mod prefabs;
 pub mod rendering;
-pub mod game_context;
\ No newline at end of file
+pub mod ecs;
+pub mod events;
+pub mod game_context;
+pub mod game_loop;
+pub mod headless;
+pub mod capture;
+pub mod replay;
+pub mod rng;
+pub mod save;
+pub mod scheduler;
+pub mod actions;
+pub mod gamepad;
\ No newline at end of file
@@ -1,10 +1,11 @@
 extern crate nalgebra as na;
 
 use na::Vector2;
-use nphysics2d::object::{DefaultBodySet, DefaultColliderSet};
+use nphysics2d::object::{DefaultBodyHandle, DefaultBodySet, DefaultColliderSet};
 use nphysics2d::force_generator::DefaultForceGeneratorSet;
 use nphysics2d::joint::DefaultJointConstraintSet;
 use nphysics2d::world::{DefaultMechanicalWorld, DefaultGeometricalWorld};
+use crate::geometry::Vec2;
 
 pub struct PhysicsWorld {
     mechanical_world: DefaultMechanicalWorld<f32>,
@@ -43,4 +44,11 @@ impl PhysicsWorld {
             &mut self.force_generators
         );
     }
+
+    pub fn body_position(&self, handle: DefaultBodyHandle) -> Option<Vec2> {
+        self.bodies
+            .rigid_body(handle)
+            .map(|b| b.position().translation.vector)
+            .map(|v| Vec2::new(v.x as f64, v.y as f64))
+    }
 }
\ No newline at end of file
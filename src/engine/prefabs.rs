@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use crate::{game::assets::Assets, geometry::Vec2};
+use super::ecs::{EntityHandle, World};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum PropValue {
+    Bool(bool),
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+impl PropValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PropValue::Bool(_) => "bool",
+            PropValue::Int(_) => "int",
+            PropValue::Float(_) => "float",
+            PropValue::String(_) => "string",
+        }
+    }
+
+    /// Converts to the type of `like`, only ints widen to floats
+    fn coerce(&self, like: &PropValue) -> Option<PropValue> {
+        match (self, like) {
+            (PropValue::Int(v), PropValue::Float(_)) => Some(PropValue::Float(*v as f32)),
+            (v, like) if v.type_name() == like.type_name() => Some(v.clone()),
+            _ => None
+        }
+    }
+}
+
+pub type PropValues = BTreeMap<String, PropValue>;
+
+/// One entry of a prefab's props schema, the type is taken from the default
+#[derive(Debug, Clone)]
+pub struct PropDef {
+    pub name: String,
+    pub default: PropValue,
+}
+
+impl PropDef {
+    pub fn new(name: &str, default: PropValue) -> Self {
+        Self {
+            name: name.to_string(),
+            default,
+        }
+    }
+}
+
+/// Props checked against the schema, every prop in the schema is present with the right type
+#[derive(Debug, Clone)]
+pub struct Props {
+    values: PropValues,
+}
+
+impl Props {
+    fn get(&self, name: &str) -> &PropValue {
+        self.values.get(name).unwrap_or_else(|| panic!("Prop '{}' is not in the prefab's schema", name))
+    }
+
+    pub fn f32(&self, name: &str) -> f32 {
+        match self.get(name) {
+            PropValue::Float(v) => *v,
+            v => panic!("Prop '{}' is a {}, not a float", name, v.type_name()),
+        }
+    }
+
+    pub fn i32(&self, name: &str) -> i32 {
+        match self.get(name) {
+            PropValue::Int(v) => *v,
+            v => panic!("Prop '{}' is a {}, not an int", name, v.type_name()),
+        }
+    }
+
+    pub fn bool(&self, name: &str) -> bool {
+        match self.get(name) {
+            PropValue::Bool(v) => *v,
+            v => panic!("Prop '{}' is a {}, not a bool", name, v.type_name()),
+        }
+    }
+
+    pub fn string(&self, name: &str) -> &str {
+        match self.get(name) {
+            PropValue::String(v) => v,
+            v => panic!("Prop '{}' is a {}, not a string", name, v.type_name()),
+        }
+    }
+
+    pub fn values(&self) -> &PropValues {
+        &self.values
+    }
+}
+
+/// Component added to everything spawned from a prefab, remembers how to spawn it again
+#[derive(Debug, Clone)]
+pub struct PrefabInstance {
+    pub name: String,
+    pub props: PropValues,
+}
+
+pub type PrefabConstructor<'a> = fn(&mut World<'a>, &'a Assets<'a>, &Props, Vec2) -> EntityHandle;
+
+pub struct Prefab<'a> {
+    pub name: String,
+    pub schema: Vec<PropDef>,
+    constructor: PrefabConstructor<'a>,
+}
+
+/// Constructors registered by name so levels, the editor and scripts can spawn objects without
+/// the engine knowing about them
+pub struct PrefabRegistry<'a> {
+    prefabs: HashMap<String, Prefab<'a>>,
+}
+
+impl<'a> PrefabRegistry<'a> {
+    pub fn new() -> Self {
+        Self {
+            prefabs: HashMap::new(),
+        }
+    }
+
+    pub fn with_prefab(mut self, name: &str, schema: Vec<PropDef>, constructor: PrefabConstructor<'a>) -> Self {
+        self.register(name, schema, constructor);
+        self
+    }
+
+    /// Registering a name twice replaces the earlier prefab
+    pub fn register(&mut self, name: &str, schema: Vec<PropDef>, constructor: PrefabConstructor<'a>) {
+        self.prefabs.insert(name.to_string(), Prefab {
+            name: name.to_string(),
+            schema,
+            constructor,
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab<'a>> {
+        self.prefabs.get(name)
+    }
+
+    /// Sorted, for listing in the editor
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.prefabs.keys().map(|n| n.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    /// Fills in defaults and checks the overrides against the schema
+    pub fn resolve(&self, name: &str, overrides: &PropValues) -> Result<Props, String> {
+        let prefab = self.get(name).ok_or(format!("Unknown prefab '{}'", name))?;
+        let mut values: PropValues = prefab.schema.iter().map(|d| (d.name.clone(), d.default.clone())).collect();
+
+        for (prop, value) in overrides.iter() {
+            let def = prefab
+                .schema
+                .iter()
+                .find(|d| d.name == *prop)
+                .ok_or(format!("Prefab '{}' has no prop '{}'", name, prop))?;
+
+            let value = value.coerce(&def.default).ok_or(format!(
+                "Prop '{}' of prefab '{}' should be a {}, got a {}",
+                prop,
+                name,
+                def.default.type_name(),
+                value.type_name()
+            ))?;
+
+            values.insert(prop.clone(), value);
+        }
+
+        Ok(Props { values })
+    }
+
+    pub fn spawn(
+        &self,
+        name: &str,
+        world: &mut World<'a>,
+        assets: &'a Assets<'a>,
+        overrides: &PropValues,
+        pos: Vec2,
+    ) -> Result<EntityHandle, String> {
+        let props = self.resolve(name, overrides)?;
+        let prefab = &self.prefabs[name];
+
+        let entity = (prefab.constructor)(world, assets, &props, pos);
+
+        world.insert(entity, PrefabInstance {
+            name: name.to_string(),
+            props: props.values,
+        });
+
+        Ok(entity)
+    }
+}
@@ -1,45 +1,473 @@
 use sdl2::{video::Window, render::Canvas};
-use super::text::FontAtlas;
+use std::cmp::Ordering;
+use crate::geometry::{SimpleRect, Vec2};
+use super::{ecs::{EntityHandle, World}, rng::Rng, text::FontAtlas};
 
 pub trait Drawable {
     fn visible(&self) -> bool { true }
     fn z_index(&self) -> i32 { 0 }
-    fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>);
+    /// World Y of the item's feet, for layers sorted with SortMode::Y
+    fn sort_y(&self) -> f64 { 0.0 }
+    /// Screen space items ignore the camera, for UI
+    fn screen_space(&self) -> bool { false }
+    fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>, camera: &Camera);
 }
 
-pub struct RenderList {
-    pub items: Vec<Box<dyn Drawable>>
+/// Maps world coordinates to the screen. `pos` is the world point shown at the centre of the view.
+pub struct Camera {
+    pub pos: Vec2,
+    pub zoom: f64,
+    pub viewport: Vec2,
+    /// Half size in world units of the box around the centre the target can move in freely
+    pub deadzone: Vec2,
+    /// How quickly the camera catches up with the target, 0 snaps to it
+    pub follow_speed: f64,
+    pub bounds: Option<SimpleRect>,
+    /// Largest shake offset in world units, reached at full trauma
+    pub max_shake: f64,
+    /// Trauma lost per second
+    pub trauma_decay: f64,
+    target: Option<EntityHandle>,
+    trauma: f64,
+    shake_offset: Vec2,
+    rng: Rng,
 }
 
-impl RenderList {
-    pub fn new() -> Self {
+impl Camera {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            pos: Vec2::new(width as f64 / 2.0, height as f64 / 2.0),
+            zoom: 1.0,
+            viewport: Vec2::new(width as f64, height as f64),
+            deadzone: Vec2::new(0.0, 0.0),
+            follow_speed: 0.0,
+            bounds: None,
+            max_shake: 8.0,
+            trauma_decay: 1.5,
+            target: None,
+            trauma: 0.0,
+            shake_offset: Vec2::new(0.0, 0.0),
+            // Fixed seed, shake doesn't touch gameplay but should look the same in replays
+            rng: Rng::new(1),
+        }
+    }
+
+    /// Camera whose world coordinates are screen pixels
+    pub fn screen(width: u32, height: u32) -> Self {
+        Self::new(width, height)
+    }
+
+    pub fn follow(&mut self, target: Option<EntityHandle>) {
+        self.target = target;
+    }
+
+    pub fn target(&self) -> Option<EntityHandle> {
+        self.target
+    }
+
+    /// Trauma is clamped to 1, the shake grows with its square so small hits stay subtle
+    pub fn add_trauma(&mut self, amount: f64) {
+        self.trauma = (self.trauma + amount).max(0.0).min(1.0);
+    }
+
+    pub fn trauma(&self) -> f64 {
+        self.trauma
+    }
+
+    /// The part of the world currently in view, ignoring shake
+    pub fn view_rect(&self) -> SimpleRect {
+        let w = self.viewport.x / self.zoom;
+        let h = self.viewport.y / self.zoom;
+        SimpleRect::new(self.pos.x - w / 2.0, self.pos.y - h / 2.0, w, h)
+    }
+
+    pub fn world_to_screen(&self, p: Vec2) -> Vec2 {
+        Vec2::new(
+            (p.x - self.pos.x - self.shake_offset.x) * self.zoom + self.viewport.x / 2.0,
+            (p.y - self.pos.y - self.shake_offset.y) * self.zoom + self.viewport.y / 2.0,
+        )
+    }
+
+    pub fn screen_to_world(&self, p: Vec2) -> Vec2 {
+        Vec2::new(
+            (p.x - self.viewport.x / 2.0) / self.zoom + self.pos.x + self.shake_offset.x,
+            (p.y - self.viewport.y / 2.0) / self.zoom + self.pos.y + self.shake_offset.y,
+        )
+    }
+
+    /// Rounds the edges rather than the size so neighbouring tiles don't open gaps when zoomed
+    pub fn to_screen_rect(&self, rect: SimpleRect) -> sdl2::rect::Rect {
+        let tl = self.world_to_screen(rect.pos);
+        let br = self.world_to_screen(Vec2::new(rect.right(), rect.bottom()));
+        let (x0, y0) = (tl.x.round() as i32, tl.y.round() as i32);
+        let (x1, y1) = (br.x.round() as i32, br.y.round() as i32);
+
+        sdl2::rect::Rect::new(x0, y0, (x1 - x0).max(1) as u32, (y1 - y0).max(1) as u32)
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        let bounds = match self.bounds {
+            Some(b) => b,
+            None => return,
+        };
+
+        let half_w = self.viewport.x / self.zoom / 2.0;
+        let half_h = self.viewport.y / self.zoom / 2.0;
+
+        // A level smaller than the view is centred instead
+        self.pos.x = if bounds.size.x <= half_w * 2.0 {
+            bounds.pos.x + bounds.size.x / 2.0
+        } else {
+            self.pos.x.max(bounds.left() + half_w).min(bounds.right() - half_w)
+        };
+
+        self.pos.y = if bounds.size.y <= half_h * 2.0 {
+            bounds.pos.y + bounds.size.y / 2.0
+        } else {
+            self.pos.y.max(bounds.top() + half_h).min(bounds.bottom() - half_h)
+        };
+    }
+
+    /// Follows the target's world position (the centre of its sprite if it has one), then
+    /// clamps to the bounds and advances the shake. Call once per tick after transforms resolve.
+    pub fn update(&mut self, world: &World<'_>, dt: f64) {
+        let target = self.target.and_then(|e| {
+            let t = world.global_transform(e)?;
+            let size = world.sprites.get(e).map(|s| s.size()).unwrap_or(Vec2::new(0.0, 0.0));
+            Some(Vec2::new(t.pos.x + size.x / 2.0, t.pos.y + size.y / 2.0))
+        });
+
+        if let Some(target) = target {
+            let desired = |pos: f64, target: f64, deadzone: f64| {
+                if target > pos + deadzone {
+                    target - deadzone
+                } else if target < pos - deadzone {
+                    target + deadzone
+                } else {
+                    pos
+                }
+            };
+
+            let dx = desired(self.pos.x, target.x, self.deadzone.x);
+            let dy = desired(self.pos.y, target.y, self.deadzone.y);
+
+            // Framerate independent smoothing, dt is the fixed tick anyway
+            let k = if self.follow_speed <= 0.0 { 1.0 } else { 1.0 - (-self.follow_speed * dt).exp() };
+
+            self.pos.x += (dx - self.pos.x) * k;
+            self.pos.y += (dy - self.pos.y) * k;
+        }
+
+        self.clamp_to_bounds();
+
+        self.trauma = (self.trauma - self.trauma_decay * dt).max(0.0);
+        let shake = self.trauma * self.trauma * self.max_shake;
+
+        self.shake_offset = Vec2::new(shake * self.rng.range(-1.0, 1.0), shake * self.rng.range(-1.0, 1.0));
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Layer {
+    Background,
+    World,
+    Foreground,
+    UI,
+}
+
+impl Layer {
+    /// Back to front
+    pub const ALL: [Layer; 4] = [Layer::Background, Layer::World, Layer::Foreground, Layer::UI];
+}
+
+/// Which camera a layer is drawn with
+pub enum LayerCamera {
+    /// The camera passed to RenderList::draw
+    Main,
+    /// Screen pixels, for UI
+    Screen,
+    Own(Camera),
+}
+
+/// How a layer orders its items, lower keys are drawn first. Keys are recomputed every frame and
+/// the layer only re-sorts when they fall out of order.
+#[derive(Copy, Clone)]
+pub enum SortMode {
+    /// By Drawable::z_index
+    Z,
+    /// By Drawable::sort_y, for top-down scenes where whatever is lower on screen is in front
+    Y,
+    Custom(fn(&dyn Drawable) -> f64),
+}
+
+impl SortMode {
+    fn key(&self, item: &dyn Drawable) -> f64 {
+        match self {
+            SortMode::Z => item.z_index() as f64,
+            SortMode::Y => item.sort_y(),
+            SortMode::Custom(key) => key(item),
+        }
+    }
+}
+
+fn cmp_keys(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+fn draw_item<'a>(item: &mut dyn Drawable, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>, camera: &Camera, screen: &Camera) {
+    if item.visible() {
+        item.draw(canvas, font_atlas, if item.screen_space() { screen } else { camera });
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RenderHandle {
+    layer: Layer,
+    index: u32,
+    generation: u32,
+}
+
+struct RenderSlot<'r> {
+    generation: u32,
+    item: Option<Box<dyn Drawable + 'r>>,
+    hidden: bool,
+    key: f64,
+}
+
+pub struct RenderLayer<'r> {
+    slots: Vec<RenderSlot<'r>>,
+    free_slots: Vec<u32>,
+    /// Removed slots still listed in `order`, only reused once the next sort drops them from it
+    removed_slots: Vec<u32>,
+    /// Slot indices in draw order
+    order: Vec<u32>,
+    dirty: bool,
+    pub camera: LayerCamera,
+    pub sort_mode: SortMode,
+}
+
+impl<'r> RenderLayer<'r> {
+    fn new(camera: LayerCamera) -> Self {
         Self {
-            items: vec![]
+            slots: vec![],
+            free_slots: vec![],
+            removed_slots: vec![],
+            order: vec![],
+            dirty: false,
+            camera,
+            sort_mode: SortMode::Z,
         }
     }
 
-    pub fn add_item(&mut self, item: Box<dyn Drawable>) {
-        self.items.push(item);
+    fn slot(&self, handle: RenderHandle) -> Option<&RenderSlot<'r>> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|s| s.generation == handle.generation && s.item.is_some())
+    }
+
+    fn slot_mut(&mut self, handle: RenderHandle) -> Option<&mut RenderSlot<'r>> {
+        self.slots
+            .get_mut(handle.index as usize)
+            .filter(|s| s.generation == handle.generation && s.item.is_some())
+    }
+
+    /// Refreshes every key and re-sorts only when items were added or removed or the keys are
+    /// out of order. The sort is stable and mostly runs on almost sorted input, so it stays cheap
+    /// for Y sorting where a few characters swap places each frame.
+    fn sort(&mut self) {
+        let mode = self.sort_mode;
+        let mut sorted = true;
+        let mut last = f64::NEG_INFINITY;
+
+        for i in self.order.iter() {
+            let slot = &mut self.slots[*i as usize];
+
+            if let Some(item) = slot.item.as_deref() {
+                slot.key = mode.key(item);
+                sorted &= slot.key >= last;
+                last = slot.key;
+            }
+        }
+
+        if sorted && !self.dirty {
+            return;
+        }
+
+        let slots = &self.slots;
+        self.order.retain(|i| slots[*i as usize].item.is_some());
+        self.order.sort_by(|a, b| cmp_keys(slots[*a as usize].key, slots[*b as usize].key));
+        self.free_slots.append(&mut self.removed_slots);
+        self.dirty = false;
+    }
+
+    /// `extra` items are drawn for this frame only, merged into the layer's order
+    fn draw<'a, 'e>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>, main: &Camera, extra: &mut [&mut (dyn Drawable + 'e)]) {
         self.sort();
+
+        let mode = self.sort_mode;
+        let mut extra_order: Vec<(f64, usize)> = extra.iter().enumerate().map(|(i, d)| (mode.key(&**d), i)).collect();
+        extra_order.sort_by(|a, b| cmp_keys(a.0, b.0));
+
+        let screen = Camera::screen(main.viewport.x as u32, main.viewport.y as u32);
+        let camera = match &self.camera {
+            LayerCamera::Main => main,
+            LayerCamera::Screen => &screen,
+            LayerCamera::Own(camera) => camera,
+        };
+
+        let mut next_extra = 0;
+
+        for i in self.order.iter() {
+            let slot = &mut self.slots[*i as usize];
+
+            while next_extra < extra_order.len() && extra_order[next_extra].0 < slot.key {
+                draw_item(&mut *extra[extra_order[next_extra].1], canvas, font_atlas, camera, &screen);
+                next_extra += 1;
+            }
+
+            if let Some(item) = slot.item.as_mut() {
+                if !slot.hidden {
+                    draw_item(&mut **item, canvas, font_atlas, camera, &screen);
+                }
+            }
+        }
+
+        for (_, i) in extra_order[next_extra..].iter() {
+            draw_item(&mut *extra[*i], canvas, font_atlas, camera, &screen);
+        }
+    }
+}
+
+/// Drawables grouped into layers, drawn back to front with each layer's camera
+pub struct RenderList<'r> {
+    layers: Vec<RenderLayer<'r>>,
+}
+
+impl<'r> RenderList<'r> {
+    pub fn new() -> Self {
+        Self {
+            layers: Layer::ALL
+                .iter()
+                .map(|l| RenderLayer::new(if *l == Layer::UI { LayerCamera::Screen } else { LayerCamera::Main }))
+                .collect(),
+        }
     }
 
-    pub fn remove_item(&mut self, item: Box<dyn Drawable>) {
-        todo!();
+    pub fn layer(&self, layer: Layer) -> &RenderLayer<'r> {
+        &self.layers[layer as usize]
     }
 
-    pub fn sort(&mut self) {
-        self.items.sort_by(|a, b| { a.z_index().cmp(&b.z_index()) });
+    pub fn layer_mut(&mut self, layer: Layer) -> &mut RenderLayer<'r> {
+        &mut self.layers[layer as usize]
     }
 
-    pub fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>) {
-        for i in self.items.iter_mut() {
-            if i.visible() {
-                i.draw(canvas, font_atlas);
+    /// Adds to the world layer
+    pub fn add_item(&mut self, item: Box<dyn Drawable + 'r>) -> RenderHandle {
+        self.add_to(Layer::World, item)
+    }
+
+    pub fn add_to(&mut self, layer: Layer, item: Box<dyn Drawable + 'r>) -> RenderHandle {
+        let l = self.layer_mut(layer);
+        let key = l.sort_mode.key(&*item);
+
+        let index = match l.free_slots.pop() {
+            Some(index) => {
+                let slot = &mut l.slots[index as usize];
+                slot.item = Some(item);
+                slot.hidden = false;
+                slot.key = key;
+                index
+            }
+            None => {
+                l.slots.push(RenderSlot { generation: 0, item: Some(item), hidden: false, key });
+                l.slots.len() as u32 - 1
             }
+        };
+
+        l.order.push(index);
+        l.dirty = true;
+
+        RenderHandle {
+            layer,
+            index,
+            generation: l.slots[index as usize].generation,
+        }
+    }
+
+    /// Gives the item back, the handle and any copies of it stop resolving
+    pub fn remove_item(&mut self, handle: RenderHandle) -> Option<Box<dyn Drawable + 'r>> {
+        let l = self.layer_mut(handle.layer);
+        let slot = l.slot_mut(handle)?;
+        let item = slot.item.take();
+
+        slot.generation = slot.generation.wrapping_add(1);
+        l.removed_slots.push(handle.index);
+        l.dirty = true;
+        item
+    }
+
+    pub fn contains(&self, handle: RenderHandle) -> bool {
+        self.layer(handle.layer).slot(handle).is_some()
+    }
+
+    pub fn get_mut(&mut self, handle: RenderHandle) -> Option<&mut (dyn Drawable + 'r)> {
+        self.layer_mut(handle.layer).slot_mut(handle).and_then(|s| s.item.as_deref_mut())
+    }
+
+    pub fn set_visible(&mut self, handle: RenderHandle, visible: bool) {
+        if let Some(slot) = self.layer_mut(handle.layer).slot_mut(handle) {
+            slot.hidden = !visible;
         }
     }
 
+    pub fn is_visible(&self, handle: RenderHandle) -> bool {
+        self.layer(handle.layer).slot(handle).map_or(false, |s| !s.hidden)
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.iter().map(|l| l.slots.len() - l.free_slots.len() - l.removed_slots.len()).sum()
+    }
+
+    pub fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>, camera: &Camera) {
+        for layer in self.layers.iter_mut() {
+            layer.draw(canvas, font_atlas, camera, &mut []);
+        }
+    }
+
+    /// Draws everything plus items that aren't kept in the list, such as ECS sprites, sorted into
+    /// `extra_layer` with its sort mode
+    pub fn draw_with<'a, 'e>(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        font_atlas: &mut FontAtlas<'a>,
+        camera: &Camera,
+        extra_layer: Layer,
+        extra: &mut [&mut (dyn Drawable + 'e)],
+    ) {
+        for (l, layer) in Layer::ALL.iter().zip(self.layers.iter_mut()) {
+            if *l == extra_layer {
+                layer.draw(canvas, font_atlas, camera, extra);
+            } else {
+                layer.draw(canvas, font_atlas, camera, &mut []);
+            }
+        }
+    }
+
+    /// Empties every layer, layer cameras are kept. Slots are freed the way remove_item does, so
+    /// handles from before never resolve to items added afterwards.
     pub fn clear(&mut self) {
-        self.items.clear();
+        for layer in self.layers.iter_mut() {
+            for (index, slot) in layer.slots.iter_mut().enumerate() {
+                if slot.item.take().is_some() {
+                    slot.generation = slot.generation.wrapping_add(1);
+                    layer.free_slots.push(index as u32);
+                }
+            }
+
+            // Nothing is left in the order, so removed slots don't have to wait for a sort
+            layer.free_slots.append(&mut layer.removed_slots);
+            layer.order.clear();
+            layer.dirty = false;
+        }
     }
-}
\ No newline at end of file
+}
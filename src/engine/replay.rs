@@ -0,0 +1,262 @@
+use sdl2::{controller::{Axis, Button}, keyboard::Keycode, mouse::MouseButton};
+use std::{fs, path::Path};
+use super::{actions::{ActionMap, BindingsConfig}, gamepad::GamepadInput, input::{InputEvent, PointerEvent}};
+
+/// The raw input events of every tick plus the RNG seed and bindings the session was started with.
+///
+/// Saved as text, a `seed N` header, optional `keys JSON` and `buttons JSON` lines holding the
+/// bindings as in gameconfig.json, then one line per tick with space separated events:
+/// `+K` / `-K` for key down / up where K is the SDL keycode number, `b+NAME` / `b-NAME` for
+/// controller buttons, `a:NAME:VALUE` for controller axes, `d` for a disconnected controller,
+/// `m:X:Y` for pointer motion, `p+B:X:Y` / `p-B:X:Y` for mouse buttons and `w:X:Y` for the wheel.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub seed: u64,
+    /// Key and button bindings the events were recorded with, played back with instead of the
+    /// player's own. None in replays saved without them.
+    pub bindings: Option<(BindingsConfig, BindingsConfig)>,
+    pub ticks: Vec<Vec<InputEvent>>,
+}
+
+impl Replay {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            bindings: None,
+            ticks: vec![],
+        }
+    }
+
+    pub fn with_bindings(mut self, actions: &ActionMap) -> Self {
+        self.bindings = Some((actions.to_config(), actions.buttons_to_config()));
+        self
+    }
+
+    /// The recorded bindings, None if the replay has none
+    pub fn action_map(&self) -> Option<Result<ActionMap, String>> {
+        self.bindings.as_ref().map(|(keys, buttons)| ActionMap::from_config(keys, buttons))
+    }
+
+    pub fn push_tick(&mut self, events: Vec<InputEvent>) {
+        self.ticks.push(events);
+    }
+
+    pub fn tick(&self, idx: usize) -> Option<&Vec<InputEvent>> {
+        self.ticks.get(idx)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let mut out = format!("seed {}\n", self.seed);
+
+        if let Some((keys, buttons)) = &self.bindings {
+            out.push_str(&format!("keys {}\n", serde_json::to_string(keys).map_err(|e| e.to_string())?));
+            out.push_str(&format!("buttons {}\n", serde_json::to_string(buttons).map_err(|e| e.to_string())?));
+        }
+
+        for events in self.ticks.iter() {
+            let line: Vec<String> = events.iter().map(encode_event).collect();
+
+            out.push_str(&line.join(" "));
+            out.push('\n');
+        }
+
+        fs::write(path, out).map_err(|e| e.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        Self::parse(&fs::read_to_string(path).map_err(|e| e.to_string())?)
+    }
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        let mut lines = contents.lines().peekable();
+
+        let seed = lines
+            .next()
+            .and_then(|l| l.strip_prefix("seed "))
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .ok_or("Replay is missing its seed header")?;
+
+        let mut ret = Self::new(seed);
+
+        if let Some(keys) = lines.peek().and_then(|l| l.strip_prefix("keys ")) {
+            let keys = serde_json::from_str(keys).map_err(|e| format!("Invalid replay key bindings: {}", e))?;
+            lines.next();
+
+            let buttons = lines
+                .next()
+                .and_then(|l| l.strip_prefix("buttons "))
+                .ok_or("Replay key bindings aren't followed by button bindings")?;
+            let buttons = serde_json::from_str(buttons).map_err(|e| format!("Invalid replay button bindings: {}", e))?;
+
+            ret.bindings = Some((keys, buttons));
+        }
+
+        for (i, line) in lines.enumerate() {
+            let mut events = vec![];
+
+            for token in line.split_whitespace() {
+                events.push(decode_event(token).ok_or(format!("Invalid event '{}' on tick {}", token, i))?);
+            }
+
+            ret.push_tick(events);
+        }
+
+        Ok(ret)
+    }
+}
+
+/// Replays of player slots other than the first are saved next to it as PATH.p2, PATH.p3, ...
+pub fn player_path(path: &str, player: usize) -> String {
+    if player == 0 { path.to_string() } else { format!("{}.p{}", path, player + 1) }
+}
+
+fn encode_event(event: &InputEvent) -> String {
+    match event {
+        InputEvent::Key(keycode, down) => format!("{}{}", if *down { '+' } else { '-' }, *keycode as i32),
+        InputEvent::Gamepad(GamepadInput::ButtonDown(button)) => format!("b+{}", button.string()),
+        InputEvent::Gamepad(GamepadInput::ButtonUp(button)) => format!("b-{}", button.string()),
+        InputEvent::Gamepad(GamepadInput::Axis(axis, value)) => format!("a:{}:{}", axis.string(), value),
+        InputEvent::Gamepad(GamepadInput::Disconnected) => "d".into(),
+        InputEvent::Pointer(PointerEvent::Move(x, y)) => format!("m:{}:{}", x, y),
+        InputEvent::Pointer(PointerEvent::Down(button, x, y)) => format!("p+{}:{}:{}", *button as u8, x, y),
+        InputEvent::Pointer(PointerEvent::Up(button, x, y)) => format!("p-{}:{}:{}", *button as u8, x, y),
+        InputEvent::Pointer(PointerEvent::Wheel(x, y)) => format!("w:{}:{}", x, y),
+    }
+}
+
+fn decode_event(token: &str) -> Option<InputEvent> {
+    if token == "d" {
+        return Some(InputEvent::Gamepad(GamepadInput::Disconnected));
+    }
+
+    if let Some(name) = token.strip_prefix("b+") {
+        return Button::from_string(name).map(|b| InputEvent::Gamepad(GamepadInput::ButtonDown(b)));
+    }
+
+    if let Some(name) = token.strip_prefix("b-") {
+        return Button::from_string(name).map(|b| InputEvent::Gamepad(GamepadInput::ButtonUp(b)));
+    }
+
+    if let Some(rest) = token.strip_prefix("a:") {
+        let mut parts = rest.splitn(2, ':');
+        let axis = parts.next().and_then(Axis::from_string)?;
+        let value = parts.next()?.parse::<i16>().ok()?;
+        return Some(InputEvent::Gamepad(GamepadInput::Axis(axis, value)));
+    }
+
+    let ints = |s: &str| -> Option<Vec<i32>> { s.split(':').map(|n| n.parse::<i32>().ok()).collect() };
+
+    if let Some(v) = token.strip_prefix("m:").and_then(ints) {
+        return match v.as_slice() {
+            [x, y] => Some(InputEvent::Pointer(PointerEvent::Move(*x, *y))),
+            _ => None,
+        };
+    }
+
+    if let Some(v) = token.strip_prefix("w:").and_then(ints) {
+        return match v.as_slice() {
+            [x, y] => Some(InputEvent::Pointer(PointerEvent::Wheel(*x, *y))),
+            _ => None,
+        };
+    }
+
+    for (prefix, down) in [("p+", true), ("p-", false)].iter() {
+        if let Some(v) = token.strip_prefix(prefix).and_then(ints) {
+            return match v.as_slice() {
+                [b, x, y] => {
+                    let button = MouseButton::from_ll(*b as u8);
+                    Some(InputEvent::Pointer(if *down {
+                        PointerEvent::Down(button, *x, *y)
+                    } else {
+                        PointerEvent::Up(button, *x, *y)
+                    }))
+                }
+                _ => None,
+            };
+        }
+    }
+
+    let down = match token.chars().next()? {
+        '+' => true,
+        '-' => false,
+        _ => return None,
+    };
+
+    token[1..]
+        .parse::<i32>()
+        .ok()
+        .and_then(Keycode::from_i32)
+        .map(|keycode| InputEvent::Key(keycode, down))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::actions::Action;
+
+    fn events() -> Vec<InputEvent> {
+        vec![
+            InputEvent::Key(Keycode::W, true),
+            InputEvent::Key(Keycode::Space, false),
+            InputEvent::Gamepad(GamepadInput::ButtonDown(Button::A)),
+            InputEvent::Gamepad(GamepadInput::ButtonUp(Button::DPadLeft)),
+            InputEvent::Gamepad(GamepadInput::Axis(Axis::LeftX, -1200)),
+            InputEvent::Gamepad(GamepadInput::Disconnected),
+            InputEvent::Pointer(PointerEvent::Move(10, -4)),
+            InputEvent::Pointer(PointerEvent::Down(MouseButton::Left, 3, 4)),
+            InputEvent::Pointer(PointerEvent::Up(MouseButton::Right, 5, 6)),
+            InputEvent::Pointer(PointerEvent::Wheel(0, -1)),
+        ]
+    }
+
+    #[test]
+    fn round_trips_every_event() {
+        for event in events() {
+            let token = encode_event(&event);
+            assert_eq!(format!("{:?}", decode_event(&token)), format!("{:?}", Some(event)), "{}", token);
+        }
+    }
+
+    #[test]
+    fn round_trips_ticks_and_bindings() {
+        let mut actions = ActionMap::default();
+        actions.bind_replacing(Action::Jump, Keycode::W);
+
+        let mut replay = Replay::new(42).with_bindings(&actions);
+        replay.push_tick(events());
+        replay.push_tick(vec![]);
+
+        let path = std::env::temp_dir().join(format!("replay_round_trip_{}", std::process::id()));
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).ok();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.seed, 42);
+        assert_eq!(format!("{:?}", loaded.ticks), format!("{:?}", replay.ticks));
+
+        let recorded = loaded.action_map().unwrap().unwrap();
+        assert_eq!(recorded.action_for(Keycode::W), Some(Action::Jump));
+    }
+
+    #[test]
+    fn loads_replays_without_bindings() {
+        let replay = Replay::parse("seed 7\n+119\n\n").unwrap();
+
+        assert!(replay.action_map().is_none());
+        assert_eq!(replay.ticks.len(), 2);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for token in ["", "x", "+", "+abc", "b+nope", "a:leftx", "a:leftx:99999", "m:1", "m:1:2:3", "p+1:2", "w:a:b"].iter() {
+            assert!(decode_event(token).is_none(), "{}", token);
+        }
+
+        assert!(Replay::parse("").is_err());
+        assert!(Replay::parse("seed x\n").is_err());
+        assert!(Replay::parse("seed 1\n+119 junk\n").is_err());
+        assert!(Replay::parse("seed 1\nkeys {}\n+119\n").is_err());
+        assert!(Replay::parse("seed 1\nkeys nope\nbuttons {}\n").is_err());
+    }
+}
@@ -0,0 +1,386 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fs, io::ErrorKind, path::{Path, PathBuf}};
+use crate::geometry::Vec2;
+use super::{
+    ecs::{EntityHandle, World},
+    prefabs::{PrefabInstance, PropValues},
+    transform::Transform,
+};
+
+/// Bump when SaveData changes shape and register a migration from the previous version
+pub const SAVE_VERSION: u32 = 2;
+
+/// The transform is local when `parent` is set and in world space otherwise
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedEntity {
+    pub prefab: String,
+    pub props: PropValues,
+    pub x: f64,
+    pub y: f64,
+    pub rotation: f64,
+    pub scale_x: f64,
+    pub scale_y: f64,
+    /// Index of the parent in SaveData::entities
+    pub parent: Option<usize>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveData {
+    pub version: u32,
+    pub level: Option<String>,
+    pub rng_state: u64,
+    /// Free-form player progress, owned by game code
+    #[serde(default)]
+    pub progress: BTreeMap<String, Value>,
+    pub entities: Vec<SavedEntity>,
+}
+
+/// Every prefab instance in the world along with its transform, tags and parent. Other components
+/// are expected to be rebuilt by the prefab from its props, entities that aren't prefab instances
+/// by the game after loading. An instance whose parent isn't saved keeps its world transform and
+/// is restored without a parent.
+pub fn save_entities(world: &World<'_>) -> Vec<SavedEntity> {
+    let saved: Vec<(EntityHandle, &PrefabInstance)> = world.query::<PrefabInstance>().collect();
+
+    saved
+        .iter()
+        .map(|(entity, instance)| {
+            let entity = *entity;
+            let parent = world.parent(entity).and_then(|p| saved.iter().position(|(e, _)| *e == p));
+
+            let transform = if parent.is_some() {
+                world.transforms.get(entity).copied()
+            } else {
+                world.global_transform(entity)
+            }
+            .unwrap_or_default();
+
+            SavedEntity {
+                prefab: instance.name.clone(),
+                props: instance.props.clone(),
+                x: transform.pos.x,
+                y: transform.pos.y,
+                rotation: transform.rotation,
+                scale_x: transform.scale.x,
+                scale_y: transform.scale.y,
+                parent,
+                tags: world.tags(entity).iter().map(|t| world.tag_name(*t).to_string()).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Parents have to be other saved entities and can't form a cycle
+pub fn check_parents(entities: &[SavedEntity]) -> Result<(), String> {
+    for (i, saved) in entities.iter().enumerate() {
+        // Walking up more parents than there are entities means a cycle
+        let mut parent = saved.parent;
+        for _ in 0..entities.len() {
+            parent = match parent {
+                Some(p) if p < entities.len() => entities[p].parent,
+                _ => break,
+            };
+        }
+
+        if parent.is_some() {
+            return Err(format!("Saved entity {} has an invalid parent", i));
+        }
+    }
+
+    Ok(())
+}
+
+/// Spawns every saved entity with `spawn`, then puts back the transforms and tags it was saved
+/// with and attaches children to their parents. Call check_parents first. Returns the new handles
+/// in the order of `entities`.
+pub fn restore_entities<'a>(
+    world: &mut World<'a>,
+    entities: &[SavedEntity],
+    mut spawn: impl FnMut(&mut World<'a>, &SavedEntity) -> Result<EntityHandle, String>,
+) -> Result<Vec<EntityHandle>, String> {
+    let mut spawned = vec![];
+
+    for saved in entities.iter() {
+        let entity = spawn(world, saved)?;
+        spawned.push(entity);
+
+        world.transforms.insert(entity, Transform {
+            pos: Vec2::new(saved.x, saved.y),
+            rotation: saved.rotation,
+            scale: Vec2::new(saved.scale_x, saved.scale_y),
+        });
+
+        // Tags added or removed since spawning
+        for tag in world.tags(entity).to_vec() {
+            world.remove_tag(entity, tag);
+        }
+        for tag in saved.tags.iter() {
+            world.add_tag(entity, tag);
+        }
+    }
+
+    // Once everything exists, attach keeps the saved local transforms
+    for (saved, entity) in entities.iter().zip(spawned.iter()) {
+        if let Some(parent) = saved.parent {
+            world.attach(*entity, spawned[parent])?;
+        }
+    }
+
+    Ok(spawned)
+}
+
+pub type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// Upgrades older save files, each migration takes the raw JSON of its version to the next one
+pub struct Migrations {
+    steps: BTreeMap<u32, Migration>,
+}
+
+/// Version 1 only saved positions
+fn add_full_transforms(save: &mut Value) -> Result<(), String> {
+    let entities = save
+        .get_mut("entities")
+        .and_then(|e| e.as_array_mut())
+        .ok_or("Save file has no entities")?;
+
+    for entity in entities.iter_mut() {
+        entity["rotation"] = Value::from(0.0);
+        entity["scale_x"] = Value::from(1.0);
+        entity["scale_y"] = Value::from(1.0);
+        entity["parent"] = Value::Null;
+    }
+
+    Ok(())
+}
+
+impl Migrations {
+    /// Starts with the engine's own migrations, games add theirs on top
+    pub fn new() -> Self {
+        Self {
+            steps: BTreeMap::new(),
+        }
+        .with_migration(1, add_full_transforms)
+    }
+
+    /// `migration` upgrades saves of version `from` to `from + 1`
+    pub fn with_migration(mut self, from: u32, migration: Migration) -> Self {
+        self.steps.insert(from, migration);
+        self
+    }
+
+    pub fn migrate(&self, mut save: Value) -> Result<SaveData, String> {
+        let mut version = save
+            .get("version")
+            .and_then(|v| v.as_u64())
+            .ok_or("Save file has no version")? as u32;
+
+        if version > SAVE_VERSION {
+            return Err(format!("Save file version {} is newer than this build ({})", version, SAVE_VERSION));
+        }
+
+        while version < SAVE_VERSION {
+            let step = self
+                .steps
+                .get(&version)
+                .ok_or(format!("No migration from save version {}", version))?;
+
+            step(&mut save)?;
+            version += 1;
+            save["version"] = Value::from(version);
+        }
+
+        serde_json::from_value(save).map_err(|e| e.to_string())
+    }
+}
+
+/// Numbered save files in one directory
+pub struct SaveSlots {
+    dir: PathBuf,
+    slots: u32,
+}
+
+impl SaveSlots {
+    pub fn new<P: AsRef<Path>>(dir: P, slots: u32) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            slots,
+        }
+    }
+
+    pub fn path(&self, slot: u32) -> PathBuf {
+        self.dir.join(format!("slot{}.json", slot))
+    }
+
+    fn check_slot(&self, slot: u32) -> Result<(), String> {
+        if slot >= self.slots {
+            return Err(format!("Save slot {} out of range, there are {}", slot, self.slots));
+        }
+        Ok(())
+    }
+
+    pub fn exists(&self, slot: u32) -> bool {
+        self.path(slot).is_file()
+    }
+
+    /// Slots that have a save in them
+    pub fn used(&self) -> Vec<u32> {
+        (0..self.slots).filter(|s| self.exists(*s)).collect()
+    }
+
+    /// Writes to a temporary file first so a crash mid-save doesn't corrupt the slot
+    pub fn save(&self, slot: u32, data: &SaveData) -> Result<(), String> {
+        self.check_slot(slot)?;
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+
+        let contents = serde_json::to_string_pretty(data).map_err(|e| e.to_string())?;
+        let tmp = self.path(slot).with_extension("json.tmp");
+
+        fs::write(&tmp, contents).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, self.path(slot)).map_err(|e| e.to_string())
+    }
+
+    /// An empty slot gives None
+    pub fn load(&self, slot: u32, migrations: &Migrations) -> Result<Option<SaveData>, String> {
+        self.check_slot(slot)?;
+
+        let contents = match fs::read_to_string(self.path(slot)) {
+            Ok(c) => c,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let raw: Value = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        migrations.migrate(raw).map(Some)
+    }
+
+    pub fn delete(&self, slot: u32) -> Result<(), String> {
+        self.check_slot(slot)?;
+
+        match fs::remove_file(self.path(slot)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn spawn_instance(world: &mut World<'_>, prefab: &str, x: f64, y: f64) -> EntityHandle {
+        let entity = world.spawn();
+        world.transforms.insert(entity, Transform::at(x, y));
+        world.insert(entity, PrefabInstance {
+            name: prefab.to_string(),
+            props: PropValues::new(),
+        });
+        entity
+    }
+
+    fn saved(parent: Option<usize>) -> SavedEntity {
+        SavedEntity {
+            prefab: "player".into(),
+            props: PropValues::new(),
+            x: 0.0,
+            y: 0.0,
+            rotation: 0.0,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            parent,
+            tags: vec![],
+        }
+    }
+
+    #[test]
+    fn round_trips_entities() {
+        let mut world = World::new();
+        let player = spawn_instance(&mut world, "player", 10.0, 20.0);
+        let hat = spawn_instance(&mut world, "hat", 12.0, 14.0);
+        world.transforms.get_mut(player).unwrap().rotation = 0.5;
+        world.add_tag(player, "player");
+        world.attach(hat, player).unwrap();
+
+        // Not a prefab instance, left out of the save
+        let loose = world.spawn();
+        world.transforms.insert(loose, Transform::at(1.0, 1.0));
+
+        let data = SaveData {
+            version: SAVE_VERSION,
+            level: Some("test_level".into()),
+            rng_state: 42,
+            progress: BTreeMap::new(),
+            entities: save_entities(&world),
+        };
+
+        let raw = serde_json::to_value(&data).unwrap();
+        let loaded = Migrations::new().migrate(raw).unwrap();
+        assert_eq!(loaded.entities.len(), 2);
+        check_parents(&loaded.entities).unwrap();
+
+        let mut restored = World::new();
+        let spawned = restore_entities(&mut restored, &loaded.entities, |world, saved| {
+            Ok(spawn_instance(world, &saved.prefab, 0.0, 0.0))
+        })
+        .unwrap();
+
+        let by_prefab = |name: &str| {
+            *spawned
+                .iter()
+                .find(|e| restored.get::<PrefabInstance>(**e).unwrap().name == name)
+                .unwrap()
+        };
+        let (player, hat) = (by_prefab("player"), by_prefab("hat"));
+
+        let t = restored.transforms.get(player).unwrap();
+        assert_eq!((t.pos.x, t.pos.y, t.rotation), (10.0, 20.0, 0.5));
+        assert_eq!(restored.parent(hat), Some(player));
+        assert_eq!(restored.parent(player), None);
+
+        let tag = restored.find_tag("player").unwrap();
+        assert!(restored.has_tag(player, tag));
+        assert!(!restored.has_tag(hat, tag));
+
+        // The child's world position survives even though the parent is rotated
+        let before = world.global_transform(world.query::<PrefabInstance>().find(|(_, i)| i.name == "hat").unwrap().0).unwrap();
+        let after = restored.global_transform(hat).unwrap();
+        assert!((before.pos.x - after.pos.x).abs() < 1e-9 && (before.pos.y - after.pos.y).abs() < 1e-9);
+    }
+
+    #[test]
+    fn migrates_version_1() {
+        let raw = json!({
+            "version": 1,
+            "level": "test_level",
+            "rng_state": 7,
+            "entities": [
+                { "prefab": "player", "props": {}, "x": 3.0, "y": 4.0, "tags": ["player"] }
+            ]
+        });
+
+        let save = Migrations::new().migrate(raw).unwrap();
+        let entity = &save.entities[0];
+
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!((entity.x, entity.y), (3.0, 4.0));
+        assert_eq!((entity.rotation, entity.scale_x, entity.scale_y), (0.0, 1.0, 1.0));
+        assert_eq!(entity.parent, None);
+        assert!(save.progress.is_empty());
+    }
+
+    #[test]
+    fn rejects_newer_and_unknown_versions() {
+        assert!(Migrations::new().migrate(json!({ "version": SAVE_VERSION + 1, "entities": [] })).is_err());
+        assert!(Migrations::new().migrate(json!({ "version": 0, "entities": [] })).is_err());
+    }
+
+    #[test]
+    fn rejects_broken_parents() {
+        assert!(check_parents(&[saved(None), saved(Some(0))]).is_ok());
+        assert!(check_parents(&[saved(Some(5))]).is_err());
+        assert!(check_parents(&[saved(Some(1)), saved(Some(0))]).is_err());
+        assert!(check_parents(&[saved(Some(0))]).is_err());
+    }
+}
@@ -1,7 +1,129 @@
 use sdl2::{video::Window, render::Canvas};
-use crate::{input::GameInput};
+use crate::{input::GameInput, text::FontAtlas};
 
-pub trait Scene {
-    fn update(&mut self, inputs: Vec<GameInput>, t: u128, dt: f64);
-    fn render(&mut self, canvas: &mut Canvas<Window>);
-}
\ No newline at end of file
+pub type SceneRef<'a> = Box<dyn Scene<'a> + 'a>;
+
+/// Returned from Scene::update to change the scene stack once the update has finished
+pub enum SceneCommand<'a> {
+    None,
+    Push(SceneRef<'a>),
+    Pop,
+    Replace(SceneRef<'a>),
+    Quit,
+}
+
+pub trait Scene<'a> {
+    /// Called once per fixed tick with each input paired with the player slot it came from.
+    /// `t` is the simulated time in milliseconds and `dt` the tick length in seconds.
+    fn update(&mut self, inputs: Vec<(usize, GameInput)>, t: u128, dt: f64) -> SceneCommand<'a>;
+    /// `alpha` is how far the frame sits between the previous and the next tick, for interpolating positions.
+    fn render(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'_>, alpha: f64);
+
+    /// Overlay scenes (pause menus, dialogs) let the scene beneath them keep rendering
+    fn is_overlay(&self) -> bool { false }
+
+    fn on_enter(&mut self) {}
+    fn on_exit(&mut self) {}
+    fn on_pause(&mut self) {}
+    fn on_resume(&mut self) {}
+}
+
+pub struct SceneManager<'a> {
+    scenes: Vec<SceneRef<'a>>
+}
+
+impl<'a> SceneManager<'a> {
+    pub fn new() -> Self {
+        Self {
+            scenes: vec![]
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scenes.len()
+    }
+
+    pub fn push(&mut self, mut scene: SceneRef<'a>) {
+        if let Some(top) = self.scenes.last_mut() {
+            top.on_pause();
+        }
+
+        scene.on_enter();
+        self.scenes.push(scene);
+    }
+
+    pub fn pop(&mut self) -> Option<SceneRef<'a>> {
+        let mut scene = self.scenes.pop()?;
+        scene.on_exit();
+
+        if let Some(top) = self.scenes.last_mut() {
+            top.on_resume();
+        }
+
+        Some(scene)
+    }
+
+    pub fn replace(&mut self, mut scene: SceneRef<'a>) -> Option<SceneRef<'a>> {
+        let mut old = self.scenes.pop();
+
+        if let Some(s) = old.as_mut() {
+            s.on_exit();
+        }
+
+        scene.on_enter();
+        self.scenes.push(scene);
+
+        old
+    }
+
+    pub fn clear(&mut self) {
+        while let Some(mut scene) = self.scenes.pop() {
+            scene.on_exit();
+        }
+    }
+
+    pub fn apply(&mut self, command: SceneCommand<'a>) {
+        match command {
+            SceneCommand::None => {}
+            SceneCommand::Push(scene) => self.push(scene),
+            SceneCommand::Pop => { self.pop(); }
+            SceneCommand::Replace(scene) => { self.replace(scene); }
+            SceneCommand::Quit => self.clear(),
+        }
+    }
+}
+
+impl<'a> Scene<'a> for SceneManager<'a> {
+    /// Only the top scene is updated, scenes below it are paused
+    fn update(&mut self, inputs: Vec<(usize, GameInput)>, t: u128, dt: f64) -> SceneCommand<'a> {
+        let command = match self.scenes.last_mut() {
+            Some(top) => top.update(inputs, t, dt),
+            None => SceneCommand::None
+        };
+
+        self.apply(command);
+
+        if self.scenes.is_empty() {
+            SceneCommand::Quit
+        } else {
+            SceneCommand::None
+        }
+    }
+
+    /// Renders bottom to top, starting from the highest scene that is not an overlay
+    fn render(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'_>, alpha: f64) {
+        let mut first = self.scenes.len().saturating_sub(1);
+
+        while first > 0 && self.scenes[first].is_overlay() {
+            first -= 1;
+        }
+
+        for scene in self.scenes.iter_mut().skip(first) {
+            scene.render(canvas, font_atlas, alpha);
+        }
+    }
+}
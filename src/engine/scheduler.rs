@@ -0,0 +1,506 @@
+use std::{collections::VecDeque, f64::consts::PI};
+use super::{ecs::{EntityHandle, World}, events::EventBus};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineInOut,
+    BackOut,
+    BounceOut,
+}
+
+impl Ease {
+    /// Maps progress in [0, 1] onto the curve, BackOut overshoots past 1 on the way
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.max(0.0).min(1.0);
+
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::QuadInOut => if t < 0.5 { 2.0 * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(2) / 2.0 },
+            Ease::CubicIn => t * t * t,
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::CubicInOut => if t < 0.5 { 4.0 * t * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0 },
+            Ease::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Ease::BackOut => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+            Ease::BounceOut => {
+                let n1 = 7.5625;
+                let d1 = 2.75;
+
+                if t < 1.0 / d1 {
+                    n1 * t * t
+                } else if t < 2.0 / d1 {
+                    let t = t - 1.5 / d1;
+                    n1 * t * t + 0.75
+                } else if t < 2.5 / d1 {
+                    let t = t - 2.25 / d1;
+                    n1 * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / d1;
+                    n1 * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+/// A value moving from one number to another over time. Usable on its own for UI values.
+#[derive(Debug, Copy, Clone)]
+pub struct Tween {
+    pub from: f64,
+    pub to: f64,
+    pub duration: f64,
+    pub ease: Ease,
+    elapsed: f64,
+}
+
+impl Tween {
+    pub fn new(from: f64, to: f64, duration: f64, ease: Ease) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            ease,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn progress(&self) -> f64 {
+        if self.duration <= 0.0 { 1.0 } else { (self.elapsed / self.duration).min(1.0) }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.from + (self.to - self.from) * self.ease.apply(self.progress())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Advances by dt seconds and returns the new value
+    pub fn update(&mut self, dt: f64) -> f64 {
+        self.elapsed += dt;
+        self.value()
+    }
+
+    /// Time past the end of the tween, carried into whatever comes next
+    fn overshoot(&self) -> f64 {
+        (self.elapsed - self.duration).max(0.0)
+    }
+}
+
+/// What a tween step animates on its entity, positions and angles are local to the parent
+#[derive(Copy, Clone)]
+pub enum Property {
+    X,
+    Y,
+    /// Rotation in degrees
+    Angle,
+    /// Sprite alpha, 0 to 255
+    Alpha,
+    Custom(fn(&World<'_>, EntityHandle) -> Option<f64>, fn(&mut World<'_>, EntityHandle, f64)),
+}
+
+impl Property {
+    fn get(&self, world: &World<'_>, entity: EntityHandle) -> Option<f64> {
+        match self {
+            Property::X => world.transforms.get(entity).map(|t| t.pos.x),
+            Property::Y => world.transforms.get(entity).map(|t| t.pos.y),
+            Property::Angle => world.transforms.get(entity).map(|t| t.rotation),
+            Property::Alpha => world.sprites.get(entity).map(|s| s.alpha as f64),
+            Property::Custom(get, _) => get(world, entity),
+        }
+    }
+
+    fn set(&self, world: &mut World<'_>, entity: EntityHandle, value: f64) {
+        match self {
+            Property::X => if let Some(t) = world.transforms.get_mut(entity) { t.pos.x = value },
+            Property::Y => if let Some(t) = world.transforms.get_mut(entity) { t.pos.y = value },
+            Property::Angle => if let Some(t) = world.transforms.get_mut(entity) { t.rotation = value },
+            Property::Alpha => if let Some(s) = world.sprites.get_mut(entity) { s.alpha = value.max(0.0).min(255.0).round() as u8 },
+            Property::Custom(_, set) => set(world, entity, value),
+        }
+    }
+}
+
+pub type Callback = fn(&mut World<'_>, &mut EventBus);
+pub type EntityCallback = fn(&mut World<'_>, &mut EventBus, EntityHandle);
+
+pub enum Step {
+    Wait(f64),
+    /// Tweens from wherever the property is when the step starts
+    Tween(EntityHandle, Property, f64, f64, Ease),
+    /// Runs the steps at the same time, finishes with the longest
+    Together(Vec<Step>),
+    Call(Callback),
+    CallWith(EntityHandle, EntityCallback),
+    Despawn(EntityHandle),
+}
+
+impl Step {
+    pub fn move_to(entity: EntityHandle, x: f64, y: f64, duration: f64, ease: Ease) -> Self {
+        Step::Together(vec![
+            Step::Tween(entity, Property::X, x, duration, ease),
+            Step::Tween(entity, Property::Y, y, duration, ease),
+        ])
+    }
+}
+
+enum Active {
+    Wait(f64),
+    Tween(EntityHandle, Property, Tween),
+    Together(Vec<Option<Active>>),
+}
+
+impl Active {
+    /// Instant steps run right away and give None
+    fn start(step: Step, world: &mut World<'_>, events: &mut EventBus) -> Option<Active> {
+        match step {
+            Step::Wait(seconds) => Some(Active::Wait(seconds)),
+            Step::Tween(entity, property, to, duration, ease) => {
+                let from = property.get(world, entity)?;
+                Some(Active::Tween(entity, property, Tween::new(from, to, duration, ease)))
+            }
+            Step::Together(steps) => {
+                let children: Vec<Option<Active>> = steps
+                    .into_iter()
+                    .map(|s| Active::start(s, world, events))
+                    .filter(|a| a.is_some())
+                    .collect();
+
+                if children.is_empty() { None } else { Some(Active::Together(children)) }
+            }
+            Step::Call(callback) => {
+                callback(world, events);
+                None
+            }
+            Step::CallWith(entity, callback) => {
+                if world.is_alive(entity) {
+                    callback(world, events, entity);
+                }
+                None
+            }
+            Step::Despawn(entity) => {
+                world.despawn(entity);
+                None
+            }
+        }
+    }
+
+    /// Gives the unused part of dt once the step is finished
+    fn advance(&mut self, dt: f64, world: &mut World<'_>) -> Option<f64> {
+        match self {
+            Active::Wait(left) => {
+                if dt >= *left {
+                    let rest = dt - *left;
+                    *left = 0.0;
+                    Some(rest)
+                } else {
+                    *left -= dt;
+                    None
+                }
+            }
+            Active::Tween(entity, property, tween) => {
+                // The entity is gone, nothing left to animate
+                if !world.is_alive(*entity) {
+                    return Some(dt);
+                }
+
+                let value = tween.update(dt);
+                property.set(world, *entity, value);
+
+                if tween.is_finished() { Some(tween.overshoot().min(dt)) } else { None }
+            }
+            Active::Together(children) => {
+                let mut rest = dt;
+                let mut finished = true;
+
+                for child in children.iter_mut() {
+                    if let Some(active) = child {
+                        match active.advance(dt, world) {
+                            Some(r) => {
+                                rest = rest.min(r);
+                                *child = None;
+                            }
+                            None => finished = false,
+                        }
+                    }
+                }
+
+                if finished { Some(rest) } else { None }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TaskId(u64);
+
+/// Shortest timer interval, anything below would fire thousands of times per tick
+pub const MIN_INTERVAL: f64 = 0.001;
+
+struct Sequence {
+    id: TaskId,
+    steps: VecDeque<Step>,
+    active: Option<Active>,
+}
+
+struct Timer {
+    id: TaskId,
+    interval: f64,
+    left: f64,
+    /// None repeats until cancelled
+    remaining: Option<u32>,
+    callback: Callback,
+}
+
+/// Delayed callbacks, repeating timers, tweens and step sequences, advanced once per fixed tick.
+/// Everything runs before the schedule's systems so events published by callbacks are delivered
+/// in the same tick.
+pub struct Scheduler {
+    next_id: u64,
+    timers: Vec<Timer>,
+    sequences: Vec<Sequence>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            next_id: 0,
+            timers: vec![],
+            sequences: vec![],
+        }
+    }
+
+    fn new_id(&mut self) -> TaskId {
+        self.next_id += 1;
+        TaskId(self.next_id)
+    }
+
+    pub fn after(&mut self, delay: f64, callback: Callback) -> TaskId {
+        self.repeat(delay, 1, callback)
+    }
+
+    /// Intervals are clamped to MIN_INTERVAL
+    pub fn every(&mut self, interval: f64, callback: Callback) -> TaskId {
+        let interval = interval.max(MIN_INTERVAL);
+        let id = self.new_id();
+        self.timers.push(Timer { id, interval, left: interval, remaining: None, callback });
+        id
+    }
+
+    /// Intervals are clamped to MIN_INTERVAL
+    pub fn repeat(&mut self, interval: f64, times: u32, callback: Callback) -> TaskId {
+        let interval = interval.max(MIN_INTERVAL);
+        let id = self.new_id();
+        self.timers.push(Timer { id, interval, left: interval, remaining: Some(times), callback });
+        id
+    }
+
+    pub fn tween(&mut self, entity: EntityHandle, property: Property, to: f64, duration: f64, ease: Ease) -> TaskId {
+        self.sequence(vec![Step::Tween(entity, property, to, duration, ease)])
+    }
+
+    /// Runs the steps one after the other, the first one starts on the next update
+    pub fn sequence(&mut self, steps: Vec<Step>) -> TaskId {
+        let id = self.new_id();
+        self.sequences.push(Sequence { id, steps: steps.into(), active: None });
+        id
+    }
+
+    pub fn cancel(&mut self, id: TaskId) {
+        self.timers.retain(|t| t.id != id);
+        self.sequences.retain(|s| s.id != id);
+    }
+
+    pub fn is_running(&self, id: TaskId) -> bool {
+        self.timers.iter().any(|t| t.id == id) || self.sequences.iter().any(|s| s.id == id)
+    }
+
+    pub fn clear(&mut self) {
+        self.timers.clear();
+        self.sequences.clear();
+    }
+
+    pub fn update(&mut self, world: &mut World<'_>, events: &mut EventBus, dt: f64) {
+        for timer in self.timers.iter_mut() {
+            timer.left -= dt;
+
+            // Short intervals can fire more than once per tick
+            while timer.left <= 0.0 && timer.remaining != Some(0) {
+                (timer.callback)(world, events);
+                timer.left += timer.interval;
+                timer.remaining = timer.remaining.map(|r| r - 1);
+            }
+        }
+
+        self.timers.retain(|t| t.remaining != Some(0));
+
+        for sequence in self.sequences.iter_mut() {
+            let mut dt = dt;
+
+            loop {
+                if sequence.active.is_none() {
+                    match sequence.steps.pop_front() {
+                        Some(step) => sequence.active = Active::start(step, world, events),
+                        None => break,
+                    }
+                    continue;
+                }
+
+                match sequence.active.as_mut().and_then(|a| a.advance(dt, world)) {
+                    Some(rest) => {
+                        sequence.active = None;
+                        dt = rest;
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        self.sequences.retain(|s| s.active.is_some() || !s.steps.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::transform::Transform;
+
+    struct Fired;
+
+    fn fire(world: &mut World<'_>, _: &mut EventBus) {
+        let entity = world.spawn();
+        world.insert(entity, Fired);
+    }
+
+    fn fire_on(world: &mut World<'_>, _: &mut EventBus, entity: EntityHandle) {
+        world.insert(entity, Fired);
+    }
+
+    fn fired(world: &World<'_>) -> usize {
+        world.with::<Fired>().len()
+    }
+
+    fn run(scheduler: &mut Scheduler, world: &mut World<'_>, dt: f64) {
+        scheduler.update(world, &mut EventBus::new(), dt);
+    }
+
+    fn spawn_at(world: &mut World<'_>, x: f64) -> EntityHandle {
+        let entity = world.spawn();
+        world.transforms.insert(entity, Transform::at(x, 0.0));
+        entity
+    }
+
+    fn x(world: &World<'_>, entity: EntityHandle) -> f64 {
+        world.transforms.get(entity).unwrap().pos.x
+    }
+
+    #[test]
+    fn fires_several_times_in_a_long_tick() {
+        let (mut scheduler, mut world) = (Scheduler::new(), World::new());
+        scheduler.every(0.25, fire);
+
+        run(&mut scheduler, &mut world, 0.875);
+        assert_eq!(fired(&world), 3);
+
+        run(&mut scheduler, &mut world, 0.125);
+        assert_eq!(fired(&world), 4);
+    }
+
+    #[test]
+    fn clamps_zero_intervals() {
+        let (mut scheduler, mut world) = (Scheduler::new(), World::new());
+        let id = scheduler.every(0.0, fire);
+
+        run(&mut scheduler, &mut world, 0.01);
+        assert!(fired(&world) <= 10);
+        assert!(scheduler.is_running(id));
+    }
+
+    #[test]
+    fn repeats_the_given_number_of_times() {
+        let (mut scheduler, mut world) = (Scheduler::new(), World::new());
+        let id = scheduler.repeat(0.1, 3, fire);
+
+        run(&mut scheduler, &mut world, 0.25);
+        assert_eq!(fired(&world), 2);
+        assert!(scheduler.is_running(id));
+
+        run(&mut scheduler, &mut world, 1.0);
+        assert_eq!(fired(&world), 3);
+        assert!(!scheduler.is_running(id));
+    }
+
+    #[test]
+    fn carries_tween_overshoot_into_the_next_step() {
+        let (mut scheduler, mut world) = (Scheduler::new(), World::new());
+        let entity = spawn_at(&mut world, 0.0);
+
+        scheduler.sequence(vec![
+            Step::Tween(entity, Property::X, 10.0, 1.0, Ease::Linear),
+            Step::Tween(entity, Property::X, 20.0, 1.0, Ease::Linear),
+        ]);
+
+        run(&mut scheduler, &mut world, 1.5);
+        assert!((x(&world, entity) - 15.0).abs() < 1e-9);
+
+        run(&mut scheduler, &mut world, 0.5);
+        assert!((x(&world, entity) - 20.0).abs() < 1e-9);
+        assert!(scheduler.sequences.is_empty());
+    }
+
+    #[test]
+    fn together_finishes_with_the_longest_step() {
+        let (mut scheduler, mut world) = (Scheduler::new(), World::new());
+        let entity = spawn_at(&mut world, 0.0);
+
+        let id = scheduler.sequence(vec![
+            Step::Together(vec![
+                Step::Tween(entity, Property::X, 10.0, 1.0, Ease::Linear),
+                Step::Wait(2.0),
+            ]),
+            Step::CallWith(entity, fire_on),
+        ]);
+
+        run(&mut scheduler, &mut world, 1.5);
+        assert!((x(&world, entity) - 10.0).abs() < 1e-9);
+        assert_eq!(fired(&world), 0);
+
+        run(&mut scheduler, &mut world, 0.5);
+        assert_eq!(fired(&world), 1);
+        assert!(!scheduler.is_running(id));
+    }
+
+    #[test]
+    fn skips_despawned_entities() {
+        let (mut scheduler, mut world) = (Scheduler::new(), World::new());
+        let entity = spawn_at(&mut world, 0.0);
+
+        let id = scheduler.sequence(vec![
+            Step::Tween(entity, Property::X, 10.0, 1.0, Ease::Linear),
+            Step::CallWith(entity, fire_on),
+            Step::Call(fire),
+        ]);
+
+        run(&mut scheduler, &mut world, 0.5);
+        world.despawn(entity);
+        world.maintain();
+        run(&mut scheduler, &mut world, 0.1);
+
+        // The tween and the entity's callback are dropped, the rest of the sequence still runs
+        assert_eq!(fired(&world), 1);
+        assert!(!scheduler.is_running(id));
+    }
+}
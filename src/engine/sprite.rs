@@ -1,28 +1,54 @@
 use crate::geometry::{SimpleRect, Vec2};
 use sdl2::{
-    render::{Canvas, Texture},
+    pixels::Color,
+    render::{BlendMode, Canvas, Texture},
     video::Window,
 };
-use super::{text::FontAtlas, rendering::Drawable};
+use std::cell::RefCell;
+use super::{text::FontAtlas, rendering::{Camera, Drawable}};
 
 pub struct Sprite<'a> {
-    tex: &'a Texture<'a>,
+    tex: &'a RefCell<Texture<'a>>,
     sdl_rect: Option<sdl2::rect::Rect>,
+    /// Part of the texture to draw, None for all of it
+    pub src_rect: Option<sdl2::rect::Rect>,
     pub rect: SimpleRect,
     pub angle: f64,
     pub flip_horizontal: bool,
     pub flip_vertical: bool,
+    /// Multiplied into the texture colour, white leaves it unchanged
+    pub tint: Color,
+    pub alpha: u8,
+    /// None keeps whatever the texture was created with
+    pub blend_mode: Option<BlendMode>,
+    /// Negative values flip the sprite on that axis
+    pub scale: Vec2,
+    /// Point of the sprite placed at `rect.pos` and rotated around, (0, 0) is the top left and
+    /// (1, 1) the bottom right. None puts the top left at `rect.pos` and rotates around the centre.
+    pub pivot: Option<Vec2>,
+    /// Scale and rotation of the entity the sprite belongs to, set by sync_sprites and applied on
+    /// top of the sprite's own
+    pub entity_scale: Vec2,
+    pub entity_angle: f64,
 }
 
 impl<'a> Sprite<'a> {
-    pub fn new(tex: &'a Texture<'a>, x: f64, y: f64, w: f64, h: f64) -> Self {
+    pub fn new(tex: &'a RefCell<Texture<'a>>, x: f64, y: f64, w: f64, h: f64) -> Self {
         let mut ret = Self {
             tex,
             sdl_rect: None,
+            src_rect: None,
             rect: SimpleRect::new(x, y, w, h),
             angle: 0.0,
             flip_horizontal: false,
             flip_vertical: false,
+            tint: Color::WHITE,
+            alpha: 255,
+            blend_mode: None,
+            scale: Vec2::new(1.0, 1.0),
+            pivot: None,
+            entity_scale: Vec2::new(1.0, 1.0),
+            entity_angle: 0.0,
         };
 
         ret.update_sdl_rect();
@@ -30,6 +56,37 @@ impl<'a> Sprite<'a> {
         return ret;
     }
 
+    /// For sprites that are one frame of a sheet or atlas
+    pub fn with_src_rect(mut self, x: i32, y: i32, w: u32, h: u32) -> Self {
+        self.src_rect = Some(sdl2::rect::Rect::new(x, y, w, h));
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: u8) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = Some(blend_mode);
+        self
+    }
+
+    pub fn with_scale(mut self, x: f64, y: f64) -> Self {
+        self.scale = Vec2::new(x, y);
+        self
+    }
+
+    pub fn with_pivot(mut self, x: f64, y: f64) -> Self {
+        self.pivot = Some(Vec2::new(x, y));
+        self
+    }
+
     pub fn pos(&self) -> Vec2 {
         self.rect.pos
     }
@@ -64,24 +121,222 @@ impl<'a> Sprite<'a> {
         ));
     }
 
+    /// The sprite's own scale combined with its entity's
+    pub fn total_scale(&self) -> Vec2 {
+        Vec2::new(self.scale.x * self.entity_scale.x, self.scale.y * self.entity_scale.y)
+    }
+
+    /// The area covered before rotation once scale and pivot are applied, in world units
+    pub fn draw_rect(&self) -> SimpleRect {
+        let scale = self.total_scale();
+        let w = self.rect.size.x * scale.x.abs();
+        let h = self.rect.size.y * scale.y.abs();
+
+        match self.pivot {
+            Some(pivot) => SimpleRect::new(self.rect.pos.x - pivot.x * w, self.rect.pos.y - pivot.y * h, w, h),
+            // Scales around the centre so unpivoted sprites stay where they were
+            None => SimpleRect::new(
+                self.rect.pos.x + (self.rect.size.x - w) / 2.0,
+                self.rect.pos.y + (self.rect.size.y - h) / 2.0,
+                w,
+                h,
+            ),
+        }
+    }
+
     pub fn clamp(&mut self, min_x: f64, min_y: f64, max_x: f64, max_y: f64) {
         self.rect.clamp(min_x, min_y, max_x, max_y);
         self.update_sdl_rect();
     }
 }
 
+/// The texture is shared between sprites, so its modulation is only set while `f` draws and put
+/// back afterwards
+fn with_texture_mods<T>(tex: &RefCell<Texture>, tint: Color, alpha: u8, blend_mode: Option<BlendMode>, f: impl FnOnce(&Texture) -> T) -> T {
+    let mut tex = tex.borrow_mut();
+    let (r, g, b) = tex.color_mod();
+    let a = tex.alpha_mod();
+    let blend = tex.blend_mode();
+
+    tex.set_color_mod(tint.r, tint.g, tint.b);
+    tex.set_alpha_mod(alpha);
+    if let Some(mode) = blend_mode {
+        tex.set_blend_mode(mode);
+    }
+
+    let ret = f(&*tex);
+
+    tex.set_color_mod(r, g, b);
+    tex.set_alpha_mod(a);
+    tex.set_blend_mode(blend);
+
+    ret
+}
+
 impl Drawable for Sprite<'_> {
-    fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>) {
-        canvas
-            .copy_ex(
-                &self.tex,
-                None,
-                self.sdl_rect,
-                self.angle,
-                None,
-                self.flip_horizontal,
-                self.flip_vertical,
+    fn visible(&self) -> bool {
+        self.alpha > 0
+    }
+
+    fn sort_y(&self) -> f64 {
+        self.draw_rect().bottom()
+    }
+
+    fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>, camera: &Camera) {
+        let draw_rect = self.draw_rect();
+        let dst = camera.to_screen_rect(draw_rect);
+        let center = self.pivot.map(|p| {
+            sdl2::rect::Point::new(
+                (p.x * draw_rect.size.x * camera.zoom).round() as i32,
+                (p.y * draw_rect.size.y * camera.zoom).round() as i32,
+            )
+        });
+
+        let scale = self.total_scale();
+
+        let result = with_texture_mods(self.tex, self.tint, self.alpha, self.blend_mode, |tex| {
+            canvas.copy_ex(
+                tex,
+                self.src_rect,
+                dst,
+                self.angle + self.entity_angle,
+                center,
+                self.flip_horizontal ^ (scale.x < 0.0),
+                self.flip_vertical ^ (scale.y < 0.0),
             )
-            .expect("Error calling canvas.copy_ex")
+        });
+
+        result.expect("Error calling canvas.copy_ex")
+    }
+}
+
+/// A bordered texture stretched to any size, the corners keep their size, the edges stretch
+/// along one axis and the centre along both
+#[derive(Clone)]
+pub struct NineSlice<'a> {
+    tex: &'a RefCell<Texture<'a>>,
+    /// Part of the texture holding the whole frame, None for all of it
+    pub src_rect: Option<sdl2::rect::Rect>,
+    /// Left, top, right and bottom border widths in texture pixels
+    pub insets: (u32, u32, u32, u32),
+    pub rect: SimpleRect,
+    /// Size of the borders on screen relative to the texture
+    pub border_scale: f64,
+    pub tint: Color,
+    pub alpha: u8,
+}
+
+impl<'a> NineSlice<'a> {
+    pub fn new(tex: &'a RefCell<Texture<'a>>, left: u32, top: u32, right: u32, bottom: u32) -> Self {
+        Self {
+            tex,
+            src_rect: None,
+            insets: (left, top, right, bottom),
+            rect: SimpleRect::new(0.0, 0.0, 0.0, 0.0),
+            border_scale: 1.0,
+            tint: Color::WHITE,
+            alpha: 255,
+        }
+    }
+
+    /// A stretched image without borders
+    pub fn image(tex: &'a RefCell<Texture<'a>>) -> Self {
+        Self::new(tex, 0, 0, 0, 0)
+    }
+
+    /// Borders taken from the centre rect of a nine-slice, like the ones Aseprite slices store
+    pub fn from_center(tex: &'a RefCell<Texture<'a>>, src_rect: Option<sdl2::rect::Rect>, center: sdl2::rect::Rect) -> Self {
+        let query = tex.borrow().query();
+        let (w, h) = src_rect.map_or((query.width, query.height), |r| (r.width(), r.height()));
+        let right = w.saturating_sub(center.x().max(0) as u32 + center.width());
+        let bottom = h.saturating_sub(center.y().max(0) as u32 + center.height());
+
+        let mut ret = Self::new(tex, center.x().max(0) as u32, center.y().max(0) as u32, right, bottom);
+        ret.src_rect = src_rect;
+        ret
+    }
+
+    pub fn with_src_rect(mut self, x: i32, y: i32, w: u32, h: u32) -> Self {
+        self.src_rect = Some(sdl2::rect::Rect::new(x, y, w, h));
+        self
+    }
+
+    pub fn with_rect(mut self, x: f64, y: f64, w: f64, h: f64) -> Self {
+        self.rect = SimpleRect::new(x, y, w, h);
+        self
+    }
+
+    pub fn with_border_scale(mut self, border_scale: f64) -> Self {
+        self.border_scale = border_scale;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: u8) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Draws into a rect in screen pixels, `scale` multiplies the border size on top of border_scale
+    pub fn draw_to(&self, canvas: &mut Canvas<Window>, dst: sdl2::rect::Rect, scale: f64) {
+        let src = self.src_rect.unwrap_or_else(|| {
+            let query = self.tex.borrow().query();
+            sdl2::rect::Rect::new(0, 0, query.width, query.height)
+        });
+        let (left, top, right, bottom) = self.insets;
+        let scale = self.border_scale * scale;
+
+        // Borders shrink together when the target is too small to fit them
+        let fit = |a: u32, b: u32, size: u32| {
+            let (a, b) = (a as f64 * scale, b as f64 * scale);
+            let shrink = if a + b > size as f64 { size as f64 / (a + b) } else { 1.0 };
+            ((a * shrink).round() as i32, (b * shrink).round() as i32)
+        };
+        let (dl, dr) = fit(left, right, dst.width());
+        let (dt, db) = fit(top, bottom, dst.height());
+
+        let src_x = [src.x(), src.x() + left as i32, src.right() - right as i32, src.right()];
+        let src_y = [src.y(), src.y() + top as i32, src.bottom() - bottom as i32, src.bottom()];
+        let dst_x = [dst.x(), dst.x() + dl, dst.right() - dr, dst.right()];
+        let dst_y = [dst.y(), dst.y() + dt, dst.bottom() - db, dst.bottom()];
+
+        with_texture_mods(self.tex, self.tint, self.alpha, None, |tex| {
+            for row in 0..3 {
+                for col in 0..3 {
+                    let (sw, sh) = (src_x[col + 1] - src_x[col], src_y[row + 1] - src_y[row]);
+                    let (dw, dh) = (dst_x[col + 1] - dst_x[col], dst_y[row + 1] - dst_y[row]);
+
+                    if sw <= 0 || sh <= 0 || dw <= 0 || dh <= 0 {
+                        continue;
+                    }
+
+                    canvas
+                        .copy(
+                            tex,
+                            sdl2::rect::Rect::new(src_x[col], src_y[row], sw as u32, sh as u32),
+                            sdl2::rect::Rect::new(dst_x[col], dst_y[row], dw as u32, dh as u32),
+                        )
+                        .expect("Error calling canvas.copy");
+                }
+            }
+        });
+    }
+}
+
+impl Drawable for NineSlice<'_> {
+    fn visible(&self) -> bool {
+        self.alpha > 0
+    }
+
+    fn sort_y(&self) -> f64 {
+        self.rect.bottom()
+    }
+
+    fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>, camera: &Camera) {
+        self.draw_to(canvas, camera.to_screen_rect(self.rect), camera.zoom);
     }
 }
\ No newline at end of file
@@ -2,8 +2,9 @@ use sdl2::{
     render::{Canvas, Texture},
     video::Window,
 };
-use crate::geometry::Vec2;
-use super::{text::FontAtlas, rendering::Drawable};
+use std::cell::RefCell;
+use crate::geometry::{SimpleRect, Vec2};
+use super::{text::FontAtlas, rendering::{Camera, Drawable}, prefabs::{PropValue, PropValues}};
 
 pub struct Tilemap<'a> {
     map: tiled::Map,
@@ -12,18 +13,26 @@ pub struct Tilemap<'a> {
     pos: Vec2
 }
 
+/// A run of touching tiles in one column of a `y_sort` layer. The run is sorted as a whole by its
+/// bottom edge, so a character standing above a tall tile's base walks behind all of it.
+pub struct TileColumn<'a> {
+    texture: &'a RefCell<Texture<'a>>,
+    tiles: Vec<(sdl2::rect::Rect, sdl2::rect::Rect)>,
+    base_y: f64,
+}
+
 pub struct Tileset<'a> {
     map_width: u32,
     map_height: u32,
     tile_width: u32,
     tile_height: u32,
     first_gid: u32, 
-    texture: &'a Texture<'a>,
+    texture: &'a RefCell<Texture<'a>>,
     texture_src_rects: Vec<sdl2::rect::Rect>
 }
 
 impl<'a> Tilemap<'a> {
-    pub fn new(x: f64, y: f64, map: &'a mut tiled::Map, tileset_texture: &'a Texture<'a>) -> Self {
+    pub fn new(x: f64, y: f64, map: &tiled::Map, tileset_texture: &'a RefCell<Texture<'a>>) -> Self {
         let map_tileset = &map.tilesets[0];
         let img = map_tileset.images.first().expect("No associated image in the tileset");
         let tile_width = map_tileset.tile_width;
@@ -57,6 +66,61 @@ impl<'a> Tilemap<'a> {
         }
     }
 
+    /// The area covered by the map in world pixels, for Camera::bounds
+    pub fn bounds(&self) -> SimpleRect {
+        SimpleRect::new(
+            self.pos.x,
+            self.pos.y,
+            (self.map.width * self.tileset.tile_width) as f64,
+            (self.map.height * self.tileset.tile_height) as f64,
+        )
+    }
+
+    /// Tiles of layers with the `y_sort` bool property, these are left out of the tilemap's own
+    /// draw so they can go into a Y-sorted layer next to the characters
+    pub fn sorted_tiles(&self) -> Vec<TileColumn<'a>> {
+        let tw = self.tileset.tile_width as i32;
+        let th = self.tileset.tile_height as i32;
+        let mut columns = vec![];
+
+        for layer in self.map.layers.iter().filter(|l| is_y_sorted(l)) {
+            let width = layer.tiles.iter().map(|row| row.len()).max().unwrap_or(0);
+
+            for tx in 0..width {
+                let mut column: Option<TileColumn<'a>> = None;
+
+                for (ty, row) in layer.tiles.iter().enumerate() {
+                    let gid = row.get(tx).map_or(0, |t| t.gid);
+
+                    if gid == 0 {
+                        columns.extend(column.take());
+                        continue;
+                    }
+
+                    let src = self.tileset.texture_src_rects[(gid - self.tileset.first_gid) as usize];
+                    let dst = sdl2::rect::Rect::new(
+                        self.pos.x as i32 + tx as i32 * tw,
+                        self.pos.y as i32 + ty as i32 * th,
+                        tw as u32,
+                        th as u32,
+                    );
+
+                    let c = column.get_or_insert_with(|| TileColumn {
+                        texture: self.tileset.texture,
+                        tiles: vec![],
+                        base_y: 0.0,
+                    });
+                    c.tiles.push((src, dst));
+                    c.base_y = dst.bottom() as f64;
+                }
+
+                columns.extend(column);
+            }
+        }
+
+        columns
+    }
+
     fn update_sdl_rects(&mut self) {
         self.sdl_rects = Tilemap::get_sdl_rects(self.pos.x, self.pos.y, self.map.clone(), &self.tileset);
     }
@@ -64,11 +128,14 @@ impl<'a> Tilemap<'a> {
     fn get_sdl_rects(x: f64, y: f64, map: tiled::Map, tileset: &Tileset) -> Vec<(sdl2::rect::Rect, sdl2::rect::Rect)> {
         let mut sdl_rects = Vec::new();
 
-        for layer in map.layers.iter() {
+        for layer in map.layers.iter().filter(|l| !is_y_sorted(l)) {
             for ty in 0..layer.tiles.len() {
                 let row = &layer.tiles[ty];
                 for tx in 0..row.len() {
                     let tile = row[tx];
+                    if tile.gid == 0 {
+                        continue;
+                    }
                     let src_idx = (tile.gid - tileset.first_gid) as usize;
                     sdl_rects.push((
                         tileset.texture_src_rects[src_idx],
@@ -82,10 +149,79 @@ impl<'a> Tilemap<'a> {
     }
 }
 
-impl Drawable for Tilemap<'_> {
-    fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>) {
-        for pair in self.sdl_rects.iter() {
-            canvas.copy(&self.tileset.texture, pair.0, pair.1).expect("canvas.copy call failed")
+fn is_y_sorted(layer: &tiled::Layer) -> bool {
+    match layer.properties.get("y_sort") {
+        Some(tiled::PropertyValue::BoolValue(v)) => *v,
+        _ => false,
+    }
+}
+
+fn draw_tiles(canvas: &mut Canvas<Window>, texture: &Texture, tiles: &[(sdl2::rect::Rect, sdl2::rect::Rect)], camera: &Camera) {
+    let view = camera.view_rect();
+
+    for pair in tiles.iter() {
+        let rect = SimpleRect::new(pair.1.x() as f64, pair.1.y() as f64, pair.1.width() as f64, pair.1.height() as f64);
+
+        // Skip tiles that are out of view
+        if rect.right() < view.left() || rect.left() > view.right() || rect.bottom() < view.top() || rect.top() > view.bottom() {
+            continue;
         }
+
+        canvas.copy(texture, pair.0, camera.to_screen_rect(rect)).expect("canvas.copy call failed")
     }
 }
+
+impl Drawable for Tilemap<'_> {
+    fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>, camera: &Camera) {
+        draw_tiles(canvas, &self.tileset.texture.borrow(), &self.sdl_rects, camera);
+    }
+}
+
+impl Drawable for TileColumn<'_> {
+    fn sort_y(&self) -> f64 {
+        self.base_y
+    }
+
+    fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>, camera: &Camera) {
+        draw_tiles(canvas, &self.texture.borrow(), &self.tiles, camera);
+    }
+}
+
+/// Custom properties of a Tiled object as prefab prop values. Colors and other property types
+/// prefabs don't know about are left out.
+pub fn object_props(object: &tiled::Object) -> PropValues {
+    object
+        .properties
+        .iter()
+        .filter(|(name, _)| name.as_str() != "type")
+        .filter_map(|(name, value)| {
+            let value = match value {
+                tiled::PropertyValue::BoolValue(v) => PropValue::Bool(*v),
+                tiled::PropertyValue::IntValue(v) => PropValue::Int(*v),
+                tiled::PropertyValue::FloatValue(v) => PropValue::Float(*v),
+                tiled::PropertyValue::StringValue(v) => PropValue::String(v.clone()),
+                _ => return None,
+            };
+            Some((name.clone(), value))
+        })
+        .collect()
+}
+
+/// The object's type, Tiled 1.9+ saves it as the class which this version of the tiled crate
+/// doesn't read, so a "type" string property works as well
+pub fn object_type(object: &tiled::Object) -> String {
+    if !object.obj_type.is_empty() {
+        return object.obj_type.clone();
+    }
+
+    match object.properties.get("type") {
+        Some(tiled::PropertyValue::StringValue(v)) => v.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Top left corner of the object in map pixels. Tile objects are anchored at their bottom left.
+pub fn object_pos(object: &tiled::Object) -> Vec2 {
+    let y = if object.gid != 0 { object.y - object.height } else { object.y };
+    Vec2::new(object.x as f64, y as f64)
+}
@@ -0,0 +1,62 @@
+use crate::geometry::Vec2;
+
+/// Position, rotation in degrees (clockwise, same as Sprite::angle) and scale.
+/// Stored relative to the parent entity, or to the world for entities without one.
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    pub pos: Vec2,
+    pub rotation: f64,
+    pub scale: Vec2,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            pos: Vec2::new(0.0, 0.0),
+            rotation: 0.0,
+            scale: Vec2::new(1.0, 1.0),
+        }
+    }
+}
+
+fn rotate(v: Vec2, degrees: f64) -> Vec2 {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+impl Transform {
+    pub fn at(x: f64, y: f64) -> Self {
+        Self {
+            pos: Vec2::new(x, y),
+            ..Self::default()
+        }
+    }
+
+    /// Moves a point from this transform's space into its parent's space
+    pub fn apply(&self, p: Vec2) -> Vec2 {
+        let r = rotate(Vec2::new(p.x * self.scale.x, p.y * self.scale.y), self.rotation);
+        Vec2::new(self.pos.x + r.x, self.pos.y + r.y)
+    }
+
+    /// `local` placed inside this transform
+    pub fn combine(&self, local: &Transform) -> Transform {
+        Transform {
+            pos: self.apply(local.pos),
+            rotation: self.rotation + local.rotation,
+            scale: Vec2::new(self.scale.x * local.scale.x, self.scale.y * local.scale.y),
+        }
+    }
+
+    /// The local transform that puts something at `world` when placed inside this transform
+    pub fn relative(&self, world: &Transform) -> Transform {
+        let d = rotate(Vec2::new(world.pos.x - self.pos.x, world.pos.y - self.pos.y), -self.rotation);
+        let sx = if self.scale.x == 0.0 { 1.0 } else { self.scale.x };
+        let sy = if self.scale.y == 0.0 { 1.0 } else { self.scale.y };
+
+        Transform {
+            pos: Vec2::new(d.x / sx, d.y / sy),
+            rotation: world.rotation - self.rotation,
+            scale: Vec2::new(world.scale.x / sx, world.scale.y / sy),
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use super::{rendering::Drawable, text::FontAtlas};
-use sdl2::{pixels::Color, render::Canvas, video::Window};
+use super::{input::PointerEvent, rendering::{Camera, Drawable}, sprite::NineSlice, text::FontAtlas};
+use sdl2::{mouse::MouseButton, pixels::Color, render::{Canvas, Texture}, video::Window};
+use std::{cell::RefCell, collections::HashMap};
 use stretch::{
     geometry::{Point, Rect, Size},
     node::Node,
@@ -10,17 +11,31 @@ use stretch::{
     },
 };
 
-pub struct UIComponent<Props, Actions>
+/// Pointer events after hit testing, carrying the name of the deepest named view under the pointer
+#[derive(Debug, Clone, PartialEq)]
+pub enum UIEvent {
+    /// Pressed and released over the same view
+    Click(String, MouseButton),
+    /// The hovered view changed, None when the pointer left every named view
+    Hover(Option<String>),
+    Wheel(String, i32, i32),
+}
+
+pub struct UIComponent<'t, Props, Actions>
 where
     Props: Copy,
 {
     props: Props,
     render: fn(props: Props) -> ViewBuilder,
     on_action: fn(props: &mut Props, action: Actions),
+    on_ui_event: Option<fn(props: &Props, event: UIEvent) -> Option<Actions>>,
     graph: UIGraph,
+    hovered: Option<String>,
+    pressed: Option<(String, MouseButton)>,
+    images: UIImages<'t>,
 }
 
-impl<Props, Actions> UIComponent<Props, Actions>
+impl<'t, Props, Actions> UIComponent<'t, Props, Actions>
 where
     Props: Copy,
 {
@@ -33,22 +48,108 @@ where
             props: initial_props,
             render,
             on_action,
+            on_ui_event: None,
             graph: UIGraph::new(render(initial_props).clone()),
+            hovered: None,
+            pressed: None,
+            images: UIImages::new(),
         }
     }
 
+    /// Images that BgImage views look up by name
+    pub fn with_images(mut self, images: UIImages<'t>) -> Self {
+        self.images = images;
+        self
+    }
+
+    /// Maps clicks, hovers and wheel events on named views to actions
+    pub fn with_ui_event_handler(mut self, on_ui_event: fn(props: &Props, event: UIEvent) -> Option<Actions>) -> Self {
+        self.on_ui_event = Some(on_ui_event);
+        self
+    }
+
     pub fn dispatch(&mut self, action: Actions) {
         (self.on_action)(&mut self.props, action);
         self.graph = UIGraph::new((self.render)(self.props).clone());
     }
+
+    /// Hit tests a pointer event (in logical coordinates) against the current layout and
+    /// dispatches whatever action the ui event handler maps it to
+    pub fn handle_pointer(&mut self, event: PointerEvent) {
+        let ui_event = match event {
+            PointerEvent::Move(x, y) => {
+                let target = self.graph.hit_test(x as f32, y as f32);
+                if target == self.hovered {
+                    None
+                } else {
+                    self.hovered = target.clone();
+                    Some(UIEvent::Hover(target))
+                }
+            }
+            PointerEvent::Down(button, x, y) => {
+                self.pressed = self.graph.hit_test(x as f32, y as f32).map(|t| (t, button));
+                None
+            }
+            PointerEvent::Up(button, x, y) => {
+                let target = self.graph.hit_test(x as f32, y as f32);
+                match (self.pressed.take(), target) {
+                    (Some((pressed, b)), Some(t)) if pressed == t && b == button => Some(UIEvent::Click(t, button)),
+                    _ => None,
+                }
+            }
+            PointerEvent::Wheel(dx, dy) => {
+                self.hovered.clone().map(|t| UIEvent::Wheel(t, dx, dy))
+            }
+        };
+
+        let action = match (ui_event, self.on_ui_event) {
+            (Some(e), Some(handler)) => handler(&self.props, e),
+            _ => None,
+        };
+
+        if let Some(a) = action {
+            self.dispatch(a);
+        }
+    }
 }
 
-impl<Props, Actions> Drawable for UIComponent<Props, Actions>
+impl<Props, Actions> Drawable for UIComponent<'_, Props, Actions>
 where
     Props: Copy,
 {
-    fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>) {
-        self.graph.draw(canvas, font_atlas);
+    fn screen_space(&self) -> bool {
+        true
+    }
+
+    fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>, camera: &Camera) {
+        self.graph.draw(canvas, font_atlas, &self.images);
+    }
+}
+
+/// Named images and nine-slices for view backgrounds. Views only hold the name so they can keep
+/// being built from plain props and static classes.
+#[derive(Default)]
+pub struct UIImages<'t> {
+    images: HashMap<String, NineSlice<'t>>,
+}
+
+impl<'t> UIImages<'t> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stretched over the whole view
+    pub fn with_image(self, name: &str, tex: &'t RefCell<Texture<'t>>) -> Self {
+        self.with_nine_slice(name, NineSlice::image(tex))
+    }
+
+    pub fn with_nine_slice(mut self, name: &str, nine_slice: NineSlice<'t>) -> Self {
+        self.images.insert(name.to_string(), nine_slice);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NineSlice<'t>> {
+        self.images.get(name)
     }
 }
 
@@ -86,14 +187,21 @@ impl UIGraph {
         self.root.compute_layout(&mut self.stretch)
     }
 
-    pub fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>) {
-        self.root.draw(&mut self.stretch, canvas, None, font_atlas);
+    pub fn draw<'a>(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'a>, images: &UIImages<'_>) {
+        self.root.draw(&mut self.stretch, canvas, None, font_atlas, images);
+    }
+
+    /// Name of the deepest named view containing the point
+    pub fn hit_test(&self, x: f32, y: f32) -> Option<String> {
+        self.root.hit_test(&self.stretch, None, x, y)
     }
 }
 
 #[derive(Default, Clone)]
 pub struct ViewStyle {
     pub background_color: Option<Color>,
+    /// Name in UIImages, drawn over the background color
+    pub background_image: Option<&'static str>,
     pub border_color: Option<Color>,
     pub border_width: Option<i32>,
     pub text: Option<String>,
@@ -111,7 +219,7 @@ pub struct UINode {
     layout_style: Style,
     children: Vec<UINode>,
     node_type: UINodeType,
-    // name: String
+    name: Option<String>,
 }
 
 impl UINode {
@@ -123,7 +231,7 @@ impl UINode {
             layout_style,
             children,
             node_type,
-            // name
+            name: None,
         }
     }
 
@@ -169,23 +277,13 @@ impl UINode {
         canvas: &mut Canvas<Window>,
         pos: Option<Point<f32>>,
         font_atlas: &mut FontAtlas<'a>,
+        images: &UIImages<'_>,
     ) {
         let layout = stretch
             .layout(self.node.expect("Must call compute_layout() first"))
             .expect("Erorr calling stretch.layout");
 
-        let cumulative_pos: Point<f32>;
-        match pos {
-            Some(v) => {
-                cumulative_pos = Point {
-                    x: v.x + layout.location.x,
-                    y: v.y + layout.location.y,
-                };
-            }
-            None => {
-                cumulative_pos = Point { x: 0.0, y: 0.0 };
-            }
-        }
+        let cumulative_pos = UINode::cumulative_pos(pos, layout);
 
         match &self.node_type {
             UINodeType::View(v) => {
@@ -221,6 +319,10 @@ impl UINode {
                     None => {}
                 }
 
+                if let Some(image) = v.background_image.and_then(|name| images.get(name)) {
+                    image.draw_to(canvas, border_box, 1.0);
+                }
+
                 match &v.text {
                     Some(s) => font_atlas.draw_str(
                         canvas,
@@ -238,7 +340,47 @@ impl UINode {
         };
 
         for child in self.children.iter_mut() {
-            child.draw(stretch, canvas, Some(cumulative_pos), font_atlas);
+            child.draw(stretch, canvas, Some(cumulative_pos), font_atlas, images);
+        }
+    }
+
+    /// The root is always drawn at 0, 0, every other node is offset from its parent
+    fn cumulative_pos(pos: Option<Point<f32>>, layout: &Layout) -> Point<f32> {
+        match pos {
+            Some(v) => Point {
+                x: v.x + layout.location.x,
+                y: v.y + layout.location.y,
+            },
+            None => Point { x: 0.0, y: 0.0 },
+        }
+    }
+
+    pub fn hit_test(
+        &self,
+        stretch: &stretch::node::Stretch,
+        pos: Option<Point<f32>>,
+        x: f32,
+        y: f32,
+    ) -> Option<String> {
+        let layout = stretch.layout(self.node?).ok()?;
+        let cumulative_pos = UINode::cumulative_pos(pos, layout);
+
+        // Later children draw on top so they get the first chance
+        for child in self.children.iter().rev() {
+            if let Some(name) = child.hit_test(stretch, Some(cumulative_pos), x, y) {
+                return Some(name);
+            }
+        }
+
+        let inside = x >= cumulative_pos.x
+            && y >= cumulative_pos.y
+            && x < cumulative_pos.x + layout.size.width
+            && y < cumulative_pos.y + layout.size.height;
+
+        if inside {
+            self.name.clone()
+        } else {
+            None
         }
     }
 }
@@ -264,6 +406,8 @@ pub enum ViewAttr {
     BgColorRGB(u8, u8, u8),
     JustifyContent(JustifyContent),
     BgColorRGBA(u8, u8, u8, u8),
+    /// Image or nine-slice registered in UIImages under this name
+    BgImage(&'static str),
     FlexBasis(Dimension),
     FlexGrow(f32),
     Width(Dimension),
@@ -298,6 +442,7 @@ pub enum ViewAttr {
     Text(String),
     FontSize(f32),
     LineHeight(f32),
+    Name(String),
 }
 
 #[derive(Clone)]
@@ -305,7 +450,8 @@ pub struct ViewBuilder {
     layout_style: Style,
     child_nodes: Vec<ViewBuilder>,
     style: ViewStyle,
-    text: Option<String>, // name: String
+    text: Option<String>,
+    name: Option<String>,
 }
 
 impl Into<UINodeBuilder> for ViewBuilder {
@@ -328,6 +474,7 @@ impl ViewBuilder {
             ViewAttr::BgColorRGBA(r, g, b, a) => {
                 self.style.background_color = Some(Color::RGBA(r, g, b, a))
             }
+            ViewAttr::BgImage(name) => self.style.background_image = Some(name),
             ViewAttr::FlexBasis(x) => self.layout_style.flex_basis = x,
             ViewAttr::FlexGrow(x) => {
                 self.layout_style.flex_grow = x;
@@ -429,6 +576,7 @@ impl ViewBuilder {
             }
             ViewAttr::FontSize(x) => self.style.font_size = Some(x),
             ViewAttr::LineHeight(x) => self.style.line_height = Some(x),
+            ViewAttr::Name(s) => self.name = Some(s),
         }
         self.clone()
     }
@@ -471,6 +619,12 @@ impl ViewBuilder {
         self.clone()
     }
 
+    /// Named views are what pointer events get hit tested against
+    pub fn name(&mut self, s: &str) -> ViewBuilder {
+        self.name = Some(s.into());
+        self.clone()
+    }
+
     pub fn text_if(&mut self, s: &str, condition: bool) -> ViewBuilder {
         if condition {
             self.style.text = Some(s.into());
@@ -479,11 +633,13 @@ impl ViewBuilder {
     }
 
     fn build(&self) -> UINode {
-        UINode::new(
+        let mut node = UINode::new(
             UINodeType::View(self.style.clone()),
             self.layout_style,
             self.child_nodes.iter().map(|child| child.build()).collect(),
-        )
+        );
+        node.name = self.name.clone();
+        node
     }
 }
 
@@ -493,7 +649,8 @@ pub fn view() -> ViewBuilder {
         layout_style: Style::default(),
         child_nodes: vec![],
         style: ViewStyle::default(),
-        text: None, // name: String::from(name)
+        text: None,
+        name: None,
     }
 }
 
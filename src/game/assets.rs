@@ -1,44 +1,65 @@
 use bytebuffer::ByteBuffer;
 use sdl2::{
-    image::LoadTexture,
+    rect::Rect,
     render::{Texture, TextureCreator, Canvas},
     video::{Window, WindowContext}, ttf::{Sdl2TtfContext},
 };
-use std::io::BufReader;
-use crate::engine::text::FontAtlas;
+use std::{cell::RefCell, io::BufReader, rc::Rc};
+use crate::engine::{animation::Animation, aseprite::{self, AsepriteFile}, text::FontAtlas};
 
 extern crate sdl2;
 
 pub struct Assets<'a> {
-    pub white_rect: Texture<'a>,
-    pub blue_rect: Texture<'a>,
-    pub red_rect: Texture<'a>,
-    pub green_rect: Texture<'a>,
-    pub tilemap: Texture<'a>,
+    pub white_rect: RefCell<Texture<'a>>,
+    pub blue_rect: RefCell<Texture<'a>>,
+    pub red_rect: RefCell<Texture<'a>>,
+    pub green_rect: RefCell<Texture<'a>>,
+    /// Clips of green_rect.ase, the player's sheet
+    pub green_rect_animation: Rc<Animation>,
+    pub tilemap: RefCell<Texture<'a>>,
+    pub panel: RefCell<Texture<'a>>,
+    /// Nine-slice centre of the panel texture
+    pub panel_center: Rect,
     pub test_level: tiled::Map,
-    pub font: FontAtlas<'a>
 }
 
-pub fn init<'a>(
-    canvas: &mut Box<Canvas<Window>>,
-    texture_creator: &'a TextureCreator<WindowContext>,
-    ttf_context: &'a Sdl2TtfContext
-) -> Result<Assets<'a>, String> {
+/// Scenes share the assets by reference, the font is kept apart since drawing text needs it mutably.
+/// Textures are in RefCells so sprites sharing one can still set its colour and alpha mods.
+pub fn init<'a>(texture_creator: &'a TextureCreator<WindowContext>) -> Result<Assets<'a>, String> {
+    let panel = AsepriteFile::load("./resources/panel.ase")?;
+    let (green_rect, green_rect_animation) = aseprite::load_animated(texture_creator, "./resources/green_rect.ase")?;
+
+    let panel_center = panel
+        .slice("panel")
+        .and_then(|s| s.key_for(0))
+        .and_then(|k| k.center)
+        .ok_or("panel.ase needs a nine-slice named \"panel\"")?;
+
     Ok(Assets {
-        white_rect: texture_creator.load_texture("./resources/white_rect.png")?,
-        blue_rect: texture_creator.load_texture("./resources/blue_rect.png")?,
-        red_rect: texture_creator.load_texture("./resources/red_rect.png")?,
-        green_rect: texture_creator.load_texture("./resources/green_rect.png")?,
-        tilemap: texture_creator.load_texture("./resources/tilemap.png")?,
+        white_rect: RefCell::new(aseprite::load_texture(texture_creator, "./resources/white_rect.ase")?),
+        blue_rect: RefCell::new(aseprite::load_texture(texture_creator, "./resources/blue_rect.ase")?),
+        red_rect: RefCell::new(aseprite::load_texture(texture_creator, "./resources/red_rect.ase")?),
+        green_rect: RefCell::new(green_rect),
+        green_rect_animation: Rc::new(green_rect_animation),
+        tilemap: RefCell::new(aseprite::load_texture(texture_creator, "./resources/tilemap.aseprite")?),
+        panel: RefCell::new(panel.to_texture(texture_creator)?),
+        panel_center,
         test_level: tiled::parse(BufReader::new(ByteBuffer::from_bytes(include_bytes!(
             "../../resources/test_level.tmx"
         )))).expect("Failed to load map"),
-        font: FontAtlas::new(
-            canvas,
-            texture_creator,
-            ttf_context,
-            "./resources/VCR_OSD_MONO_1.001.ttf",
-            128
-        )
     })
 }
+
+pub fn font<'a>(
+    canvas: &mut Box<Canvas<Window>>,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    ttf_context: &'a Sdl2TtfContext
+) -> FontAtlas<'a> {
+    FontAtlas::new(
+        canvas,
+        texture_creator,
+        ttf_context,
+        "./resources/VCR_OSD_MONO_1.001.ttf",
+        128
+    )
+}
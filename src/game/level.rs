@@ -0,0 +1,139 @@
+use sdl2::{render::Canvas, video::Window};
+use crate::{
+    actions::Action,
+    constants::*,
+    game_context::{GameContext, Level},
+    input::GameInput,
+    rendering::{Layer, SortMode},
+    save::{Migrations, SaveSlots},
+    scene::{Scene, SceneCommand},
+    text::FontAtlas,
+    tiles::Tilemap,
+};
+use super::{assets::Assets, player::Player, prefabs, ui_test};
+
+/// Slot used by QuickSave and QuickLoad
+const QUICK_SAVE_SLOT: u32 = 0;
+
+/// Plays test_level.tmx, the map's object layer places the entities
+pub struct LevelScene<'a> {
+    assets: &'a Assets<'a>,
+    context: GameContext<'a>,
+    saves: SaveSlots,
+    migrations: Migrations,
+}
+
+impl<'a> LevelScene<'a> {
+    pub fn new(assets: &'a Assets<'a>, seed: u64) -> Result<Self, String> {
+        let mut ret = Self {
+            assets,
+            context: prefabs::context(assets, seed),
+            saves: SaveSlots::new(SAVE_DIR, SAVE_SLOTS),
+            migrations: Migrations::new(),
+        };
+
+        ret.load("test_level")?;
+        Ok(ret)
+    }
+
+    fn map(&self, name: &str) -> Result<&'a tiled::Map, String> {
+        match name {
+            "test_level" => Ok(&self.assets.test_level),
+            _ => Err(format!("Unknown level '{}'", name)),
+        }
+    }
+
+    fn load(&mut self, name: &str) -> Result<(), String> {
+        let map = self.map(name)?;
+        self.context.load_level(&Level::from_tiled(name, map)?)?;
+
+        let tilemap = Tilemap::new(0.0, 0.0, map, &self.assets.tilemap);
+        self.context.camera.bounds = Some(tilemap.bounds());
+
+        // Tall tiles are sorted with the characters, top-down whatever is lower is in front
+        self.context.render_list.layer_mut(Layer::World).sort_mode = SortMode::Y;
+        for column in tilemap.sorted_tiles() {
+            self.context.render_list.add_to(Layer::World, Box::new(column));
+        }
+
+        self.context.render_list.add_to(Layer::Background, Box::new(tilemap));
+
+        self.follow_player();
+        Ok(())
+    }
+
+    fn follow_player(&mut self) {
+        let player = self.context.get_objects_by_tag("player").next();
+        self.context.camera.follow(player);
+    }
+
+    fn quick_save(&self) -> Result<(), String> {
+        self.saves.save(QUICK_SAVE_SLOT, &self.context.save_state())
+    }
+
+    /// Switches to the saved level first if it isn't the current one, restoring only replaces the
+    /// entities
+    fn quick_load(&mut self) -> Result<(), String> {
+        let save = match self.saves.load(QUICK_SAVE_SLOT, &self.migrations)? {
+            Some(save) => save,
+            None => return Ok(()),
+        };
+
+        let level = save.level.clone().ok_or("The save has no level")?;
+        if self.context.level() != Some(level.as_str()) {
+            self.load(&level)?;
+        }
+
+        self.context.restore_state(&save)?;
+        self.follow_player();
+        Ok(())
+    }
+
+    /// Moves each Player entity with the input of its slot
+    fn move_players(&mut self, slot: usize, dx: f64, dy: f64, dt: f64) {
+        let world = &mut self.context.world;
+
+        for entity in world.with::<Player>() {
+            let speed = match world.get::<Player>(entity) {
+                Some(player) if player.props.slot == slot => player.props.speed as f64,
+                _ => continue,
+            };
+
+            if let Some(t) = world.transforms.get_mut(entity) {
+                t.pos.x += dx * speed * dt;
+                t.pos.y += dy * speed * dt;
+            }
+        }
+    }
+}
+
+impl<'a> Scene<'a> for LevelScene<'a> {
+    fn update(&mut self, inputs: Vec<(usize, GameInput)>, t: u128, dt: f64) -> SceneCommand<'a> {
+        let mut command = SceneCommand::None;
+
+        for (player, input) in inputs {
+            match input {
+                GameInput::Move(dx, dy) => self.move_players(player, dx, dy, dt),
+                GameInput::Menu => command = SceneCommand::Push(Box::new(ui_test::GameScene::new(self.assets))),
+                GameInput::Pressed(Action::QuickSave) => {
+                    if let Err(e) = self.quick_save() {
+                        eprintln!("Failed to save: {}", e);
+                    }
+                }
+                GameInput::Pressed(Action::QuickLoad) => {
+                    if let Err(e) = self.quick_load() {
+                        eprintln!("Failed to load the save: {}", e);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.context.update(dt);
+        command
+    }
+
+    fn render(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'_>, _alpha: f64) {
+        self.context.render(canvas, font_atlas);
+    }
+}
@@ -1,2 +1,5 @@
 pub mod assets;
-pub mod player;
\ No newline at end of file
+pub mod level;
+pub mod player;
+pub mod prefabs;
+pub mod ui_test;
@@ -1,31 +1,45 @@
-use crate::engine::{sprite::Sprite, game_context::GameObject};
+use std::rc::Rc;
+use crate::{engine::{animation::Animator, sprite::Sprite, ecs::{EntityHandle, World}, prefabs::{PropDef, PropValue, Props}, transform::Transform}, geometry::Vec2};
 use super::assets::Assets;
 
 #[derive(Debug, Copy, Clone)]
 pub struct PlayerProps {
+    pub speed: f32,
+    /// Player slot whose input moves this player
+    pub slot: usize,
+}
 
+impl Default for PlayerProps {
+    fn default() -> Self {
+        Self { speed: 120.0, slot: 0 }
+    }
 }
 
-pub struct Player<'a> {
-    sprite: Sprite<'a>
+/// Marks the entity controlled by the player
+#[derive(Debug, Copy, Clone)]
+pub struct Player {
+    pub props: PlayerProps
 }
 
-impl<'a> Player<'a> {
-    pub fn new(props: PlayerProps, assets: &'a Assets<'a>) -> Self {
-        Self {
-            sprite: Sprite::new(&assets.green_rect, 0.0 ,0.0, 32.0, 32.0)
-        }
-    }
+pub fn schema() -> Vec<PropDef> {
+    vec![
+        PropDef::new("speed", PropValue::Float(PlayerProps::default().speed)),
+        PropDef::new("slot", PropValue::Int(PlayerProps::default().slot as i32)),
+    ]
 }
 
-impl<'a> GameObject for Player<'a> {
-    fn tags(&self) -> Vec<String> {
-        vec![]
-    }
+pub fn prefab<'a>(world: &mut World<'a>, assets: &'a Assets<'a>, props: &Props, pos: Vec2) -> EntityHandle {
+    spawn(world, PlayerProps { speed: props.f32("speed"), slot: props.i32("slot").max(0) as usize }, assets, pos.x, pos.y)
+}
 
-    fn update(&mut self) {
-    }
+pub fn spawn<'a>(world: &mut World<'a>, props: PlayerProps, assets: &'a Assets<'a>, x: f64, y: f64) -> EntityHandle {
+    let entity = world.spawn();
 
-    fn set_pos(&mut self, x: f32, y: f32) {
-    }
-}
\ No newline at end of file
+    world.transforms.insert(entity, Transform::at(x, y));
+    world.sprites.insert(entity, Sprite::new(&assets.green_rect, x, y, 32.0, 32.0));
+    world.insert(entity, Animator::new(Rc::clone(&assets.green_rect_animation), "default"));
+    world.add_tag(entity, "player");
+    world.insert(entity, Player { props });
+
+    entity
+}
@@ -0,0 +1,13 @@
+use crate::engine::{game_context::GameContext, prefabs::PrefabRegistry};
+use super::{assets::Assets, player};
+
+/// Every prefab the game's levels can place
+pub fn registry<'a>() -> PrefabRegistry<'a> {
+    PrefabRegistry::new()
+        .with_prefab("player", player::schema(), player::prefab)
+}
+
+/// A GameContext that can spawn everything in registry(), scenes should create theirs through this
+pub fn context<'a>(assets: &'a Assets<'a>, seed: u64) -> GameContext<'a> {
+    GameContext::new(assets, seed).with_prefabs(registry())
+}
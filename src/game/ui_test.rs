@@ -2,8 +2,15 @@ use super::assets::Assets;
 use crate::constants::*;
 use crate::ui::ViewAttr::*;
 use crate::ui::*;
-use crate::{define_class, input::GameInput, scene::Scene};
-use sdl2::{render::Canvas, video::Window};
+use crate::{
+    define_class,
+    input::GameInput,
+    rendering::{Camera, Drawable},
+    scene::{Scene, SceneCommand},
+    sprite::NineSlice,
+    text::FontAtlas,
+};
+use sdl2::{pixels::Color, render::Canvas, video::Window};
 
 #[derive(Debug, Copy, Clone)]
 pub struct UIProps {
@@ -12,6 +19,7 @@ pub struct UIProps {
 
 enum UIActions {
     MoveCursor(i8),
+    Select(i8),
 }
 
 define_class!(
@@ -19,13 +27,24 @@ define_class!(
     [WidthPx(SCREEN_WIDTH as f32), HeightPx(SCREEN_HEIGHT as f32)]
 );
 
+define_class!(
+    PANEL,
+    [BgImage("panel"), MarginPx(10.0, 10.0, 10.0, 10.0), PaddingPx(8.0, 8.0, 8.0, 8.0)]
+);
+
 pub struct GameScene<'a> {
-    assets: Assets<'a>,
-    ui: UIComponent<UIProps, UIActions>,
+    assets: &'a Assets<'a>,
+    ui: UIComponent<'a, UIProps, UIActions>,
 }
 
 impl<'a> GameScene<'a> {
-    pub fn new(assets: Assets<'a>) -> Self {
+    pub fn new(assets: &'a Assets<'a>) -> Self {
+        let panel = NineSlice::from_center(&assets.panel, None, assets.panel_center).with_border_scale(2.0);
+        let images = UIImages::new()
+            .with_nine_slice("panel", panel.clone())
+            .with_nine_slice("button", panel.clone().with_tint(Color::RGB(150, 150, 150)))
+            .with_nine_slice("button_selected", panel);
+
         Self {
             assets,
             ui: UIComponent::new(
@@ -34,6 +53,9 @@ impl<'a> GameScene<'a> {
                     UIActions::MoveCursor(direction) => {
                         props.selected_idx = (props.selected_idx + direction).max(0).min(3);
                     }
+                    UIActions::Select(idx) => {
+                        props.selected_idx = idx.max(0).min(3);
+                    }
                 },
                 |props| {
                     let menu_options = vec![
@@ -43,11 +65,12 @@ impl<'a> GameScene<'a> {
                         "Flee",
                     ];
 
-                    fn menuitem(text: &str, selected: bool) -> ViewBuilder {
+                    fn menuitem(idx: usize, text: &str, selected: bool) -> ViewBuilder {
                         view()
+                            .name(&format!("menuitem:{}", idx))
                             .attr(MarginPx(10.0, 10.0, 10.0, 10.0))
-                            .attr_if(BgColorRGB(180, 180, 180), selected)
-                            .attr_if(BgColorRGB(120, 120, 120), !selected)
+                            .attr_if(BgImage("button_selected"), selected)
+                            .attr_if(BgImage("button"), !selected)
                             .attr(PaddingPx(20.0, 20.0, 20.0, 20.0))
                             .child(
                                 view()
@@ -66,44 +89,64 @@ impl<'a> GameScene<'a> {
                             view() // Left column
                                 .attr(FlexGrow(0.4))
                                 .attr(FlexDirection(stretch::style::FlexDirection::Column))
-                                .attr(BgColorRGB(100, 100, 100))
-                                .attr(MarginPx(10.0, 10.0, 10.0, 10.0))
+                                .class(PANEL)
                                 .children(
                                     &mut ((0..(menu_options.len()))
                                         .map(|i| {
-                                            menuitem(menu_options[i], props.selected_idx == i as i8)
+                                            menuitem(i, menu_options[i], props.selected_idx == i as i8)
                                         })
                                         .collect()),
                                 ),
                             view() // Center view
                                 .attr(FlexGrow(1.0))
                                 .attr(FlexDirection(stretch::style::FlexDirection::Column))
-                                .attr(BgColorRGB(100, 100, 100))
-                                .attr(MarginPx(10.0, 10.0, 10.0, 10.0)),
+                                .class(PANEL),
                             view() // Right Column
                                 .attr(FlexGrow(0.4))
-                                .attr(MarginPx(10.0, 10.0, 10.0, 10.0))
                                 .attr(FlexDirection(stretch::style::FlexDirection::Column))
-                                .attr(BgColorRGB(100, 100, 100))
+                                .class(PANEL)
                         ])
                 },
-            ),
+            )
+            .with_ui_event_handler(|_props, event| {
+                let target = match &event {
+                    UIEvent::Click(name, _) => Some(name.as_str()),
+                    UIEvent::Hover(name) => name.as_deref(),
+                    _ => None,
+                };
+
+                target
+                    .and_then(|name| name.strip_prefix("menuitem:"))
+                    .and_then(|idx| idx.parse::<i8>().ok())
+                    .map(UIActions::Select)
+            })
+            .with_images(images),
         }
     }
 }
 
-impl<'a> Scene for GameScene<'a> {
-    fn update(&mut self, inputs: Vec<GameInput>, t: u128, dt: f64) {
-        for input in inputs {
+impl<'a> Scene<'a> for GameScene<'a> {
+    fn update(&mut self, inputs: Vec<(usize, GameInput)>, t: u128, dt: f64) -> SceneCommand<'a> {
+        // Any player can drive the menu
+        for (_, input) in inputs {
             match input {
                 GameInput::Up => self.ui.dispatch(UIActions::MoveCursor(-1)),
                 GameInput::Down => self.ui.dispatch(UIActions::MoveCursor(1)),
+                GameInput::Pointer(event) => self.ui.handle_pointer(event),
+                GameInput::Cancel => return SceneCommand::Pop,
                 _ => {}
             }
         }
+
+        SceneCommand::None
+    }
+
+    /// Opened over the level, which stays visible behind the panels
+    fn is_overlay(&self) -> bool {
+        true
     }
 
-    fn render(&mut self, canvas: &mut Canvas<Window>) {
-        self.ui.draw(canvas, &mut self.assets.font);
+    fn render(&mut self, canvas: &mut Canvas<Window>, font_atlas: &mut FontAtlas<'_>, _alpha: f64) {
+        self.ui.draw(canvas, font_atlas, &Camera::screen(SCREEN_WIDTH, SCREEN_HEIGHT));
     }
 }
@@ -1,5 +0,0 @@
-use crate::game::player::PlayerProps;
-
-pub enum GameObjectType {
-    Player(PlayerProps)
-}
\ No newline at end of file
@@ -5,40 +5,66 @@
 mod game;
 mod engine;
 mod editor;
-mod game_object_types;
 mod constants;
+mod options;
+mod config;
 
 extern crate sdl2;
 
 use sdl2::{event::Event, image::{InitFlag}, keyboard::Keycode, ttf, pixels::Color, rect::Rect};
-use std::time::{SystemTime};
 use engine::*;
 use game::*;
-use input::InputManager;
-use scene::Scene;
+use input::{InputManager, PointerEvent};
+use scene::{Scene, SceneManager};
 use assets::Assets;
 use constants::*;
 use rendering::Drawable;
+use game_loop::GameLoop;
+use options::Options;
+use capture::Frame;
+use replay::Replay;
+use config::GameConfig;
+use actions::ActionMap;
+use gamepad::GamepadManager;
 
 #[macro_use]
 extern crate lazy_static;
 
 
 pub fn main() {
+    let options = Options::from_args(std::env::args()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+
+    if options.headless {
+        sdl2::hint::set("SDL_VIDEODRIVER", "dummy");
+    }
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
+    let game_controller_subsystem = sdl_context.game_controller().unwrap();
     let _image_context = sdl2::image::init(InitFlag::PNG | InitFlag::JPG).unwrap();
     let ttf_context = ttf::init().map_err(|e| e.to_string()).unwrap();
 
-    let window = video_subsystem.window("rust-sdl2 demo", SCREEN_WIDTH, SCREEN_HEIGHT)
-        .position_centered()
-        .resizable()
+    let mut window_builder = video_subsystem.window("rust-sdl2 demo", SCREEN_WIDTH, SCREEN_HEIGHT);
+    window_builder.position_centered().resizable();
+
+    if options.headless {
+        window_builder.hidden();
+    }
+
+    let window = window_builder
         .build()
         .unwrap();
 
-    let mut canvas = Box::new(window
-        .into_canvas()
-        .present_vsync()
+    let canvas_builder = if options.headless {
+        window.into_canvas().software()
+    } else {
+        window.into_canvas().present_vsync()
+    };
+
+    let mut canvas = Box::new(canvas_builder
         .build()
         .unwrap());
 
@@ -47,59 +73,178 @@ pub fn main() {
         .expect("Error setting canvas logical size");
 
     let texture_creator = canvas.texture_creator();
-    let mut assets = assets::init(&mut canvas, &texture_creator, &ttf_context).expect("Failed to load assets");
+    // The RefCells make Assets invariant, so scenes can only borrow it for as long as the texture
+    // creator if it is never dropped. SDL frees the textures along with the renderer.
+    let assets: &Assets = Box::leak(Box::new(assets::init(&texture_creator).expect("Failed to load assets")));
+    let mut font = assets::font(&mut canvas, &texture_creator, &ttf_context);
 
     let mut event_pump = sdl_context.event_pump().unwrap();
-    let mut input_manager = InputManager::new();
+    let mut game_config = GameConfig::load(GAME_CONFIG_PATH).expect("Failed to load game config");
 
-    let now = SystemTime::now();
-    let mut last_tick_t: Option<u128> = None;
+    // One InputManager per player slot, the keyboard and mouse drive player one
+    let mut players: Vec<InputManager> = (0..MAX_PLAYERS).map(|_| InputManager::new()).collect();
+
+    if let Err(e) = ActionMap::from_config(&game_config.bindings, &game_config.buttons) {
+        eprintln!("Invalid bindings in game config, using the defaults: {}", e);
+        game_config.bindings.clear();
+        game_config.buttons.clear();
+    }
+
+    for input_manager in players.iter_mut() {
+        input_manager.actions = ActionMap::from_config(&game_config.bindings, &game_config.buttons).unwrap_or_default();
+        input_manager.deadzone = game_config.deadzone();
+    }
+
+    let mut gamepads = GamepadManager::new(game_controller_subsystem, MAX_PLAYERS);
+
+    if let Some(path) = &options.replay {
+        let replay = Replay::load(path).expect("Failed to load replay");
+
+        // Every slot replays, even without a file of its own, so live input can't leak in
+        for (player, input_manager) in players.iter_mut().enumerate().skip(1) {
+            let player_path = replay::player_path(path, player);
+            input_manager.start_replay(if std::path::Path::new(&player_path).exists() {
+                Replay::load(&player_path).expect("Failed to load replay")
+            } else {
+                Replay::new(replay.seed)
+            }).expect("Failed to start replay");
+        }
+
+        players[0].start_replay(replay).expect("Failed to start replay");
+    }
+
+    if options.record.is_some() {
+        for input_manager in players.iter_mut() {
+            input_manager.start_recording();
+        }
+    }
+
+    let mut game_loop = GameLoop::new(TICK_RATE).with_max_ticks_per_frame(MAX_TICKS_PER_FRAME);
+    let mut scene_manager = SceneManager::new();
+    // Created after the replay is loaded so it rolls the recorded seed
+    scene_manager.push(Box::new(level::LevelScene::new(assets, players[0].seed()).expect("Failed to load level")));
 
     // let mut game_editor = editor::Editor::new();
 
+    let mut frame = 0;
+    let mut exit_code = 0;
+
     'running: loop {
-        let dt: f64;
-
-        match last_tick_t {
-            Some(last_t) => {
-                let t = now.elapsed().unwrap().as_millis();
-                dt = (t as f64 - last_t as f64) / FPS;
-                last_tick_t = Some(t);
-            },
-            None => {
-                let t = now.elapsed().unwrap().as_millis();
-                dt = 1.0;
-                last_tick_t = Some(t);
-            }
+        if options.frames.map_or(false, |frames| frame >= frames) || players[0].is_replay_finished() {
+            break 'running
         }
 
         canvas.set_draw_color((0, 0, 0));
         canvas.clear();
-        // assets.font.draw_str(&mut canvas, "This is a test. Lorum ipsum dolor set amut.", 100, 100, 300, 300, 0.5, 1.0);
+        // font.draw_str(&mut canvas, "This is a test. Lorum ipsum dolor set amut.", 100, 100, 300, 300, 0.5, 1.0);
 
         for event in event_pump.poll_iter() {
+            match gamepads.handle_event(&event) {
+                Ok(Some((player, input))) => players[player].process_gamepad(input),
+                Ok(None) => {}
+                Err(e) => eprintln!("{}", e),
+            }
+
             match event {
                 Event::Quit {..} |
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
-                Event::KeyDown { keycode, .. } => {
-                    input_manager.process_keydown(keycode.unwrap())
+                // Key repeat is handled per action by the InputManager, ignore the OS repeats
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                    players[0].process_keydown(keycode)
+                }
+                Event::KeyUp { keycode: Some(keycode), .. } => {
+                    players[0].process_keyup(keycode)
+                }
+                // SDL has already scaled mouse coordinates to the canvas logical size
+                Event::MouseMotion { x, y, .. } => {
+                    players[0].process_pointer(PointerEvent::Move(x, y))
                 }
-                Event::KeyUp { keycode, .. } => {
-                    input_manager.process_keyup(keycode.unwrap())
+                Event::MouseButtonDown { mouse_btn, x, y, .. } => {
+                    players[0].process_pointer(PointerEvent::Down(mouse_btn, x, y))
+                }
+                Event::MouseButtonUp { mouse_btn, x, y, .. } => {
+                    players[0].process_pointer(PointerEvent::Up(mouse_btn, x, y))
+                }
+                Event::MouseWheel { x, y, .. } => {
+                    players[0].process_pointer(PointerEvent::Wheel(x, y))
                 }
                 _ => {}
             }
         }
 
-        let t = last_tick_t.unwrap();
+        let running = if options.headless {
+            options.input.apply(frame, &mut players[0]);
+            game_loop.run_fixed_frame(&mut scene_manager, &mut players, &mut canvas, &mut font)
+        } else {
+            game_loop.run_frame(&mut scene_manager, &mut players, &mut canvas, &mut font)
+        };
 
-        let inputs = input_manager.collect_game_inputs();
+        if !running {
+            break 'running
+        }
 
         // game_editor.update(inputs);
-        // game_editor.ui.draw(&mut canvas, &mut assets.font);
+        // game_editor.ui.draw(&mut canvas, &mut font);
+
+        if options.frames == Some(frame + 1) {
+            exit_code = check_last_frame(&options, &canvas);
+        }
 
         canvas.present();
+        frame += 1;
+    }
+
+    if let Some(path) = &options.record {
+        for (player, input_manager) in players.iter_mut().enumerate() {
+            match input_manager.stop_recording() {
+                // Slots nobody played in are left out
+                Some(recording) if player == 0 || recording.ticks.iter().any(|t| !t.is_empty()) => {
+                    recording.save(replay::player_path(path, player)).expect("Failed to save replay");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if players[0].actions.is_dirty() {
+        game_config.bindings = players[0].actions.to_config();
+        game_config.buttons = players[0].actions.buttons_to_config();
+        game_config.save(GAME_CONFIG_PATH).expect("Failed to save game config");
     }
+
+    if options.headless || exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+}
+
+fn check_last_frame(options: &Options, canvas: &sdl2::render::Canvas<sdl2::video::Window>) -> i32 {
+    if options.capture.is_none() && options.golden.is_none() {
+        return 0;
+    }
+
+    let last_frame = match Frame::capture(canvas) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("Failed to capture frame: {}", e);
+            return 1;
+        }
+    };
+
+    if let Some(path) = &options.capture {
+        if let Err(e) = last_frame.save_png(path) {
+            eprintln!("Failed to save {}: {}", path, e);
+            return 1;
+        }
+    }
+
+    if let Some(path) = &options.golden {
+        if let Err(e) = last_frame.compare_to_golden(path, options.tolerance) {
+            eprintln!("{}", e);
+            return 1;
+        }
+    }
+
+    0
 }
\ No newline at end of file
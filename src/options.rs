@@ -0,0 +1,72 @@
+use crate::headless::SyntheticInput;
+
+pub const DEFAULT_HEADLESS_FRAMES: u32 = 600;
+
+/// Command line options
+///
+///   --headless          run on a hidden window with the software renderer, no GPU or display needed
+///   --frames=N          stop after N frames (defaults to 600 when headless)
+///   --press=FRAME:KEY   press KEY on FRAME, e.g. --press=30:Down (headless only)
+///   --capture=PATH      save the last frame as a PNG
+///   --golden=PATH       compare the last frame against a golden PNG, exits with 1 on mismatch
+///   --tolerance=N       per channel difference allowed when comparing against the golden
+///   --record=PATH       record every tick's input and the RNG seed to a replay file
+///                       (other player slots go to PATH.p2, PATH.p3, ...)
+///   --replay=PATH       play back a replay file instead of live input, stops when it runs out
+pub struct Options {
+    pub headless: bool,
+    pub frames: Option<u32>,
+    pub input: SyntheticInput,
+    pub capture: Option<String>,
+    pub golden: Option<String>,
+    pub tolerance: u8,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+}
+
+impl Options {
+    pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut ret = Self {
+            headless: false,
+            frames: None,
+            input: SyntheticInput::new(),
+            capture: None,
+            golden: None,
+            tolerance: 0,
+            record: None,
+            replay: None,
+        };
+
+        for arg in args.skip(1) {
+            let mut parts = arg.splitn(2, '=');
+            let name = parts.next().unwrap_or("");
+            let value = parts.next();
+
+            match (name, value) {
+                ("--headless", None) => ret.headless = true,
+                ("--frames", Some(v)) => {
+                    ret.frames = Some(v.parse().map_err(|_| format!("Invalid frame count '{}'", v))?);
+                }
+                ("--press", Some(v)) => ret.input.parse_press(v)?,
+                ("--capture", Some(v)) => ret.capture = Some(v.into()),
+                ("--golden", Some(v)) => ret.golden = Some(v.into()),
+                ("--tolerance", Some(v)) => {
+                    ret.tolerance = v.parse().map_err(|_| format!("Invalid tolerance '{}'", v))?;
+                }
+                ("--record", Some(v)) => ret.record = Some(v.into()),
+                ("--replay", Some(v)) => ret.replay = Some(v.into()),
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+
+        if ret.headless && ret.frames.is_none() {
+            ret.frames = Some(DEFAULT_HEADLESS_FRAMES);
+        }
+
+        if (ret.capture.is_some() || ret.golden.is_some()) && ret.frames.is_none() {
+            return Err("--capture and --golden need --frames or --headless to know which frame to read".into());
+        }
+
+        Ok(ret)
+    }
+}
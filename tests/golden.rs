@@ -0,0 +1,17 @@
+use std::process::Command;
+
+/// Runs the game headless for a couple of frames and compares the last one against a checked-in
+/// image. On mismatch level.actual.png and level.diff.png are written next to the golden, rerun
+/// with UPDATE_GOLDEN=1 to accept the new frame. The black rows at the top and bottom of the
+/// golden aren't letterboxing, the 13 tile high map is shorter than the screen and the camera
+/// centres it.
+#[test]
+fn test_level_matches_golden() {
+    let status = Command::new(env!("CARGO_BIN_EXE_game"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(&["--headless", "--frames=2", "--golden=tests/golden/level.png"])
+        .status()
+        .expect("Failed to run the game");
+
+    assert!(status.success(), "The last frame did not match tests/golden/level.png");
+}